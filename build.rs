@@ -0,0 +1,10 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(mobile)");
+    // Android and iOS share platform specific socket behaviour
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if os == "android" || os == "ios" {
+        println!("cargo:rustc-cfg=mobile");
+    }
+}
@@ -1,33 +1,221 @@
 use std::env;
 use std::path::PathBuf;
 
-#[allow(dead_code)]
 fn env_var_rerun(name: &str) -> Result<String, env::VarError> {
     println!("cargo:rerun-if-env-changed={}", name);
     env::var(name)
 }
 
-fn main() {
-    let out_dir = env::var("OUT_DIR").unwrap();
+/// Check whether cargo feature is enabled
+fn feature(name: &str) -> bool {
+    env::var(format!("CARGO_FEATURE_{}", name)).is_ok()
+}
+
+/// Get target cfg value, e.g. "os" or "env"
+fn target(key: &str) -> String {
+    env::var(format!("CARGO_CFG_TARGET_{}", key.to_uppercase())).unwrap_or_default()
+}
+
+/// Android ABI name for the target architecture
+fn android_abi() -> &'static str {
+    match target("arch").as_str() {
+        "aarch64" => "arm64-v8a",
+        "arm" => "armeabi-v7a",
+        "x86_64" => "x86_64",
+        "x86" => "x86",
+        arch => panic!("unsupported Android architecture {}", arch),
+    }
+}
+
+/// Android NDK location
+fn android_ndk() -> PathBuf {
+    env_var_rerun("ANDROID_NDK_HOME")
+        .or_else(|_| env_var_rerun("ANDROID_NDK_ROOT"))
+        .map(PathBuf::from)
+        .expect("set ANDROID_NDK_HOME to cross-compile for Android")
+}
+
+/// Android API level, getifaddrs used for gathering is available since 24
+fn android_platform() -> String {
+    env_var_rerun("ANDROID_PLATFORM").unwrap_or_else(|_| "android-24".to_string())
+}
 
+/// Pass cross-compilation settings for mobile targets
+fn configure_mobile(config: &mut cmake::Config) {
+    match target("os").as_str() {
+        "android" => {
+            let toolchain = android_ndk().join("build/cmake/android.toolchain.cmake");
+            config.define("CMAKE_TOOLCHAIN_FILE", toolchain);
+            config.define("ANDROID_ABI", android_abi());
+            config.define("ANDROID_PLATFORM", android_platform());
+        }
+        "ios" => {
+            let arch = target("arch");
+            let simulator = target("abi") == "sim" || arch == "x86_64";
+            let arch = if arch == "aarch64" { "arm64" } else { &arch };
+            config.define("CMAKE_SYSTEM_NAME", "iOS");
+            config.define("CMAKE_OSX_ARCHITECTURES", arch);
+            config.define(
+                "CMAKE_OSX_SYSROOT",
+                if simulator {
+                    "iphonesimulator"
+                } else {
+                    "iphoneos"
+                },
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Version declared by bundled libjuice CMake project
+fn bundled_version() -> Option<String> {
+    let cmake = std::fs::read_to_string("libjuice/CMakeLists.txt").ok()?;
+    let project = &cmake[cmake.find("project(")?..];
+    let project = &project[..project.find(')')?];
+    let mut tokens = project.split_whitespace();
+    tokens.find(|token| *token == "VERSION")?;
+    tokens.next().map(str::to_string)
+}
+
+/// Pass linked libjuice version to the crate
+fn emit_version(version: Option<String>) {
+    if let Some(version) = version {
+        println!("cargo:rustc-env=LIBJUICE_VERSION={}", version);
+    }
+}
+
+/// CMake boolean option value
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Build bundled libjuice and link it statically, returns include paths
+fn build_bundled(out_dir: &str) -> Vec<PathBuf> {
     let mut config = cmake::Config::new("libjuice");
     config.build_target("juice-static");
-    config.out_dir(&out_dir);
+    config.out_dir(out_dir);
     config.define("NO_EXPORT_HEADER", "ON");
     config.define("NO_TESTS", "ON");
+    config.define("NO_SERVER", on_off(!feature("SERVER")));
+    config.define("USE_NETTLE", on_off(feature("NETTLE")));
+    config.define(
+        "ENABLE_LOCALHOST_ADDRESS",
+        on_off(feature("LOCALHOST_ADDRESS")),
+    );
+    config.define(
+        "ENABLE_LOCAL_ADDRESS_TRANSLATION",
+        on_off(feature("LOCAL_ADDRESS_TRANSLATION")),
+    );
+    configure_mobile(&mut config);
     config.build();
 
-    // Link static libjuice
-    let path = if cfg!(windows) {
+    // Only Visual Studio generator puts artifacts into per-profile directory, MinGW doesn't
+    let path = if target("env") == "msvc" {
         format!("{}/build/{}", out_dir, config.get_profile())
     } else {
         format!("{}/build", out_dir)
     };
     println!("cargo:rustc-link-search=native={}", path);
     println!("cargo:rustc-link-lib=static=juice-static");
+    link_static_deps();
+    println!("cargo:rustc-cfg=juice_bundled");
+    emit_version(bundled_version());
+
+    vec![PathBuf::from("libjuice/include")]
+}
 
+/// Link system libraries static libjuice depends on
+fn link_static_deps() {
+    if feature("NETTLE") {
+        println!("cargo:rustc-link-lib=nettle");
+    }
+    if target("os") == "windows" {
+        println!("cargo:rustc-link-lib=ws2_32");
+        println!("cargo:rustc-link-lib=bcrypt");
+    }
+}
+
+/// Find installed libjuice, returns include paths
+fn find_system() -> Vec<PathBuf> {
+    match pkg_config::Config::new()
+        .atleast_version("1.1")
+        .probe("libjuice")
+    {
+        Ok(lib) => {
+            emit_version(Some(lib.version));
+            return lib.include_paths;
+        }
+        Err(e) => println!("cargo:warning=libjuice not found with pkg-config: {}", e),
+    }
+
+    if target("env") == "msvc" {
+        match vcpkg::find_package("libjuice") {
+            Ok(lib) => return lib.include_paths,
+            Err(e) => println!("cargo:warning=libjuice not found with vcpkg: {}", e),
+        }
+    }
+
+    // Explicit location, e.g. MinGW build without pkg-config
+    let lib_dir = env_var_rerun("LIBJUICE_LIB_DIR")
+        .expect("libjuice not found, set LIBJUICE_LIB_DIR and LIBJUICE_INCLUDE_DIR");
+    println!("cargo:rustc-link-search=native={}", lib_dir);
+    if env_var_rerun("LIBJUICE_STATIC").is_ok() {
+        // CMake names static target "juice-static" for every toolchain
+        println!("cargo:rustc-link-lib=static=juice-static");
+        link_static_deps();
+    } else {
+        // juice.lib for MSVC, libjuice.dll.a or libjuice.so otherwise
+        println!("cargo:rustc-link-lib=dylib=juice");
+    }
+
+    env_var_rerun("LIBJUICE_INCLUDE_DIR")
+        .map(|dir| vec![PathBuf::from(dir)])
+        .unwrap_or_default()
+}
+
+/// Clang target and sysroot when cross-compiling
+#[cfg(feature = "buildtime-bindgen")]
+fn clang_target_args() -> Vec<String> {
+    let target_triple = env::var("TARGET").unwrap();
+    if target_triple == env::var("HOST").unwrap() {
+        return vec![];
+    }
+
+    let mut args = vec![format!("--target={}", target_triple)];
+    if target("os") == "android" {
+        let host_tag = match env::consts::OS {
+            "macos" => "darwin-x86_64",
+            "windows" => "windows-x86_64",
+            _ => "linux-x86_64",
+        };
+        let sysroot = android_ndk()
+            .join("toolchains/llvm/prebuilt")
+            .join(host_tag)
+            .join("sysroot");
+        args.push(format!("--sysroot={}", sysroot.display()));
+    }
+    args
+}
+
+/// Generate bindings into OUT_DIR
+#[cfg(feature = "buildtime-bindgen")]
+fn generate_bindings(out_dir: &str, include_paths: &[PathBuf]) {
     let bindings = bindgen::Builder::default()
-        .header("libjuice/include/juice/juice.h")
+        .header_contents("wrapper.h", "#include <juice/juice.h>")
+        .clang_args(
+            include_paths
+                .iter()
+                .map(|path| format!("-I{}", path.display())),
+        )
+        .clang_args(clang_target_args())
+        // keep in sync with pregenerated src/bindings.rs
+        .size_t_is_usize(true)
+        .layout_tests(false)
         .generate()
         .expect("Unable to generate bindings");
 
@@ -36,3 +224,29 @@ fn main() {
         .write_to_file(out_path.join("bindings.rs"))
         .expect("Couldn't write bindings");
 }
+
+/// Check the layout assumed by the bindings against the C header, fails the build on mismatch
+fn check_layout(include_paths: &[PathBuf]) {
+    println!("cargo:rerun-if-changed=src/layout.c");
+    cc::Build::new()
+        .file("src/layout.c")
+        .includes(include_paths)
+        .compile("juice_sys_layout");
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    println!("cargo:rustc-check-cfg=cfg(juice_bundled)");
+    println!("cargo:rerun-if-changed=libjuice");
+
+    let system = feature("SYSTEM") || env_var_rerun("LIBJUICE_SYS_USE_PKG_CONFIG").is_ok();
+    let include_paths = if system {
+        find_system()
+    } else {
+        build_bundled(&out_dir)
+    };
+
+    check_layout(&include_paths);
+    #[cfg(feature = "buildtime-bindgen")]
+    generate_bindings(&out_dir, &include_paths);
+}
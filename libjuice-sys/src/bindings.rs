@@ -0,0 +1,186 @@
+/* automatically generated by rust-bindgen 0.59.2 */
+
+// Generated from libjuice/include/juice/juice.h (libjuice 1.1) with `size_t_is_usize` and
+// without layout tests, so the same file fits every target. Enable `buildtime-bindgen` feature
+// to regenerate at build time. Struct layouts and enum values are checked against the header
+// being built with by src/layout.c, regenerate when it fails after a submodule update.
+
+pub const JUICE_ERR_SUCCESS: u32 = 0;
+pub const JUICE_ERR_INVALID: i32 = -1;
+pub const JUICE_ERR_FAILED: i32 = -2;
+pub const JUICE_ERR_NOT_AVAIL: i32 = -3;
+pub const JUICE_MAX_ADDRESS_STRING_LEN: u32 = 64;
+pub const JUICE_MAX_CANDIDATE_SDP_STRING_LEN: u32 = 256;
+pub const JUICE_MAX_SDP_STRING_LEN: u32 = 4096;
+pub type size_t = usize;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_agent {
+    _unused: [u8; 0],
+}
+pub type juice_agent_t = juice_agent;
+pub const juice_state_JUICE_STATE_DISCONNECTED: juice_state = 0;
+pub const juice_state_JUICE_STATE_GATHERING: juice_state = 1;
+pub const juice_state_JUICE_STATE_CONNECTING: juice_state = 2;
+pub const juice_state_JUICE_STATE_CONNECTED: juice_state = 3;
+pub const juice_state_JUICE_STATE_COMPLETED: juice_state = 4;
+pub const juice_state_JUICE_STATE_FAILED: juice_state = 5;
+pub type juice_state = ::std::os::raw::c_uint;
+pub use self::juice_state as juice_state_t;
+pub type juice_cb_state_changed_t = ::std::option::Option<
+    unsafe extern "C" fn(
+        agent: *mut juice_agent_t,
+        state: juice_state_t,
+        user_ptr: *mut ::std::os::raw::c_void,
+    ),
+>;
+pub type juice_cb_candidate_t = ::std::option::Option<
+    unsafe extern "C" fn(
+        agent: *mut juice_agent_t,
+        sdp: *const ::std::os::raw::c_char,
+        user_ptr: *mut ::std::os::raw::c_void,
+    ),
+>;
+pub type juice_cb_gathering_done_t = ::std::option::Option<
+    unsafe extern "C" fn(agent: *mut juice_agent_t, user_ptr: *mut ::std::os::raw::c_void),
+>;
+pub type juice_cb_recv_t = ::std::option::Option<
+    unsafe extern "C" fn(
+        agent: *mut juice_agent_t,
+        data: *const ::std::os::raw::c_char,
+        size: size_t,
+        user_ptr: *mut ::std::os::raw::c_void,
+    ),
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_turn_server {
+    pub host: *const ::std::os::raw::c_char,
+    pub username: *const ::std::os::raw::c_char,
+    pub password: *const ::std::os::raw::c_char,
+    pub port: u16,
+}
+pub type juice_turn_server_t = juice_turn_server;
+pub const juice_concurrency_mode_JUICE_CONCURRENCY_MODE_POLL: juice_concurrency_mode = 0;
+pub const juice_concurrency_mode_JUICE_CONCURRENCY_MODE_MUX: juice_concurrency_mode = 1;
+pub const juice_concurrency_mode_JUICE_CONCURRENCY_MODE_THREAD: juice_concurrency_mode = 2;
+pub type juice_concurrency_mode = ::std::os::raw::c_uint;
+pub use self::juice_concurrency_mode as juice_concurrency_mode_t;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_config {
+    pub concurrency_mode: juice_concurrency_mode_t,
+    pub stun_server_host: *const ::std::os::raw::c_char,
+    pub stun_server_port: u16,
+    pub turn_servers: *mut juice_turn_server_t,
+    pub turn_servers_count: ::std::os::raw::c_int,
+    pub bind_address: *const ::std::os::raw::c_char,
+    pub local_port_range_begin: u16,
+    pub local_port_range_end: u16,
+    pub cb_state_changed: juice_cb_state_changed_t,
+    pub cb_candidate: juice_cb_candidate_t,
+    pub cb_gathering_done: juice_cb_gathering_done_t,
+    pub cb_recv: juice_cb_recv_t,
+    pub user_ptr: *mut ::std::os::raw::c_void,
+}
+pub type juice_config_t = juice_config;
+extern "C" {
+    pub fn juice_create(config: *const juice_config_t) -> *mut juice_agent_t;
+    pub fn juice_destroy(agent: *mut juice_agent_t);
+    pub fn juice_gather_candidates(agent: *mut juice_agent_t) -> ::std::os::raw::c_int;
+    pub fn juice_get_local_description(
+        agent: *mut juice_agent_t,
+        buffer: *mut ::std::os::raw::c_char,
+        size: size_t,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_set_remote_description(
+        agent: *mut juice_agent_t,
+        sdp: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_add_remote_candidate(
+        agent: *mut juice_agent_t,
+        sdp: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_set_remote_gathering_done(agent: *mut juice_agent_t) -> ::std::os::raw::c_int;
+    pub fn juice_send(
+        agent: *mut juice_agent_t,
+        data: *const ::std::os::raw::c_char,
+        size: size_t,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_send_diffserv(
+        agent: *mut juice_agent_t,
+        data: *const ::std::os::raw::c_char,
+        size: size_t,
+        ds: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_get_state(agent: *mut juice_agent_t) -> juice_state_t;
+    pub fn juice_get_selected_candidates(
+        agent: *mut juice_agent_t,
+        local: *mut ::std::os::raw::c_char,
+        local_size: size_t,
+        remote: *mut ::std::os::raw::c_char,
+        remote_size: size_t,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_get_selected_addresses(
+        agent: *mut juice_agent_t,
+        local: *mut ::std::os::raw::c_char,
+        local_size: size_t,
+        remote: *mut ::std::os::raw::c_char,
+        remote_size: size_t,
+    ) -> ::std::os::raw::c_int;
+    pub fn juice_state_to_string(state: juice_state_t) -> *const ::std::os::raw::c_char;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_server {
+    _unused: [u8; 0],
+}
+pub type juice_server_t = juice_server;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_server_credentials {
+    pub username: *const ::std::os::raw::c_char,
+    pub password: *const ::std::os::raw::c_char,
+    pub allocations_quota: ::std::os::raw::c_int,
+}
+pub type juice_server_credentials_t = juice_server_credentials;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct juice_server_config {
+    pub credentials: *mut juice_server_credentials_t,
+    pub credentials_count: ::std::os::raw::c_int,
+    pub max_allocations: ::std::os::raw::c_int,
+    pub max_peers: ::std::os::raw::c_int,
+    pub bind_address: *const ::std::os::raw::c_char,
+    pub external_address: *const ::std::os::raw::c_char,
+    pub port: u16,
+    pub relay_port_range_begin: u16,
+    pub relay_port_range_end: u16,
+    pub realm: *const ::std::os::raw::c_char,
+}
+pub type juice_server_config_t = juice_server_config;
+extern "C" {
+    pub fn juice_server_create(config: *const juice_server_config_t) -> *mut juice_server_t;
+    pub fn juice_server_destroy(server: *mut juice_server_t);
+    pub fn juice_server_get_port(server: *mut juice_server_t) -> u16;
+    pub fn juice_server_add_credentials(
+        server: *mut juice_server_t,
+        credentials: *const juice_server_credentials_t,
+        lifetime_ms: ::std::os::raw::c_ulong,
+    ) -> ::std::os::raw::c_int;
+}
+pub const juice_log_level_t_JUICE_LOG_LEVEL_VERBOSE: juice_log_level_t = 0;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_DEBUG: juice_log_level_t = 1;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_INFO: juice_log_level_t = 2;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_WARN: juice_log_level_t = 3;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_ERROR: juice_log_level_t = 4;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_FATAL: juice_log_level_t = 5;
+pub const juice_log_level_t_JUICE_LOG_LEVEL_NONE: juice_log_level_t = 6;
+pub type juice_log_level_t = ::std::os::raw::c_uint;
+pub type juice_log_cb_t = ::std::option::Option<
+    unsafe extern "C" fn(level: juice_log_level_t, message: *const ::std::os::raw::c_char),
+>;
+extern "C" {
+    pub fn juice_set_log_level(level: juice_log_level_t);
+    pub fn juice_set_log_handler(cb: juice_log_cb_t);
+}
@@ -0,0 +1,35 @@
+/*
+ * Compile-time check of the C header against the layout assumed by src/bindings.rs, the same
+ * offsets are asserted on the Rust side in src/lib.rs. Compiled by build.rs, produces no code.
+ */
+#include <stddef.h>
+
+#include <juice/juice.h>
+
+#define PTR sizeof(void *)
+#define CHECK(cond) _Static_assert(cond, #cond)
+
+CHECK(JUICE_CONCURRENCY_MODE_POLL == 0);
+CHECK(JUICE_CONCURRENCY_MODE_MUX == 1);
+CHECK(JUICE_CONCURRENCY_MODE_THREAD == 2);
+
+CHECK(offsetof(juice_turn_server_t, host) == 0);
+CHECK(offsetof(juice_turn_server_t, username) == PTR);
+CHECK(offsetof(juice_turn_server_t, password) == 2 * PTR);
+CHECK(offsetof(juice_turn_server_t, port) == 3 * PTR);
+CHECK(sizeof(juice_turn_server_t) == 4 * PTR);
+
+CHECK(offsetof(juice_config_t, concurrency_mode) == 0);
+CHECK(offsetof(juice_config_t, stun_server_host) == PTR);
+CHECK(offsetof(juice_config_t, stun_server_port) == 2 * PTR);
+CHECK(offsetof(juice_config_t, turn_servers) == 3 * PTR);
+CHECK(offsetof(juice_config_t, turn_servers_count) == 4 * PTR);
+CHECK(offsetof(juice_config_t, bind_address) == 5 * PTR);
+CHECK(offsetof(juice_config_t, local_port_range_begin) == 6 * PTR);
+CHECK(offsetof(juice_config_t, local_port_range_end) == 6 * PTR + 2);
+CHECK(offsetof(juice_config_t, cb_state_changed) == 7 * PTR);
+CHECK(offsetof(juice_config_t, cb_candidate) == 8 * PTR);
+CHECK(offsetof(juice_config_t, cb_gathering_done) == 9 * PTR);
+CHECK(offsetof(juice_config_t, cb_recv) == 10 * PTR);
+CHECK(offsetof(juice_config_t, user_ptr) == 11 * PTR);
+CHECK(sizeof(juice_config_t) == 12 * PTR);
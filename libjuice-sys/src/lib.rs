@@ -2,4 +2,56 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+#[cfg(feature = "buildtime-bindgen")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+#[cfg(not(feature = "buildtime-bindgen"))]
+include!("bindings.rs");
+
+// Layout the pregenerated bindings assume, build.rs checks the same offsets against the C
+// header with src/layout.c.
+const PTR: usize = std::mem::size_of::<*const u8>();
+const _: () = {
+    use std::mem::{offset_of, size_of};
+
+    assert!(juice_concurrency_mode_JUICE_CONCURRENCY_MODE_POLL == 0);
+    assert!(juice_concurrency_mode_JUICE_CONCURRENCY_MODE_MUX == 1);
+    assert!(juice_concurrency_mode_JUICE_CONCURRENCY_MODE_THREAD == 2);
+
+    assert!(offset_of!(juice_turn_server, host) == 0);
+    assert!(offset_of!(juice_turn_server, username) == PTR);
+    assert!(offset_of!(juice_turn_server, password) == 2 * PTR);
+    assert!(offset_of!(juice_turn_server, port) == 3 * PTR);
+    assert!(size_of::<juice_turn_server>() == 4 * PTR);
+
+    assert!(offset_of!(juice_config, concurrency_mode) == 0);
+    assert!(offset_of!(juice_config, stun_server_host) == PTR);
+    assert!(offset_of!(juice_config, stun_server_port) == 2 * PTR);
+    assert!(offset_of!(juice_config, turn_servers) == 3 * PTR);
+    assert!(offset_of!(juice_config, turn_servers_count) == 4 * PTR);
+    assert!(offset_of!(juice_config, bind_address) == 5 * PTR);
+    assert!(offset_of!(juice_config, local_port_range_begin) == 6 * PTR);
+    assert!(offset_of!(juice_config, local_port_range_end) == 6 * PTR + 2);
+    assert!(offset_of!(juice_config, cb_state_changed) == 7 * PTR);
+    assert!(offset_of!(juice_config, cb_candidate) == 8 * PTR);
+    assert!(offset_of!(juice_config, cb_gathering_done) == 9 * PTR);
+    assert!(offset_of!(juice_config, cb_recv) == 10 * PTR);
+    assert!(offset_of!(juice_config, user_ptr) == 11 * PTR);
+    assert!(size_of::<juice_config>() == 12 * PTR);
+};
+
+// Build options below reflect enabled features, installed library linked with `system` feature
+// may be built differently.
+
+/// Embedded server is compiled in
+pub const BUILD_SERVER: bool = cfg!(feature = "server");
+/// Nettle hashing backend is used
+pub const BUILD_NETTLE: bool = cfg!(feature = "nettle");
+/// Loopback addresses are gathered
+pub const BUILD_LOCALHOST_ADDRESS: bool = cfg!(feature = "localhost-address");
+/// Local address translation is enabled
+pub const BUILD_LOCAL_ADDRESS_TRANSLATION: bool = cfg!(feature = "local-address-translation");
+/// Bundled libjuice is built, so build options above are exact
+pub const BUILD_BUNDLED: bool = cfg!(juice_bundled);
+
+/// Version of the linked libjuice, e.g. "1.2.3", if known
+pub const LIBJUICE_VERSION: Option<&str> = option_env!("LIBJUICE_VERSION");
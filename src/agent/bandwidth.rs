@@ -0,0 +1,167 @@
+//! Data path throughput and jitter estimation.
+use std::time::{Duration, Instant};
+
+/// Rate sampling interval
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Weight of a new rate sample
+const RATE_GAIN: f64 = 0.2;
+/// Jitter smoothing divisor (RFC 3550)
+const JITTER_DIVISOR: f64 = 16.0;
+
+/// Smoothed data path estimates.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandwidthEstimate {
+    /// Achieved send throughput, bits per second
+    pub send_rate: f64,
+    /// Achieved receive throughput, bits per second
+    pub recv_rate: f64,
+    /// Inter-arrival delay variation of received packets
+    pub jitter: Duration,
+}
+
+/// Exponentially smoothed rate meter.
+#[derive(Default)]
+struct RateMeter {
+    window_start: Option<Instant>,
+    bytes: u64,
+    rate: f64,
+}
+
+impl RateMeter {
+    /// Account packet, returns true if rate was updated
+    fn on_packet(&mut self, len: usize, now: Instant) -> bool {
+        let start = *self.window_start.get_or_insert(now);
+        self.bytes += len as u64;
+
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= SAMPLE_INTERVAL {
+            let sample = self.bytes as f64 * 8.0 / elapsed.as_secs_f64();
+            self.rate += RATE_GAIN * (sample - self.rate);
+            self.window_start = Some(now);
+            self.bytes = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Rate at `now`, decayed by the sample intervals passed since the last packet
+    fn rate(&self, now: Instant) -> f64 {
+        let elapsed = match self.window_start {
+            Some(start) => now.saturating_duration_since(start),
+            None => return self.rate,
+        };
+        let intervals = (elapsed.as_nanos() / SAMPLE_INTERVAL.as_nanos()) as i32;
+        if intervals == 0 {
+            return self.rate;
+        }
+        let sample = self.bytes as f64 * 8.0 / elapsed.as_secs_f64();
+        let keep = (1.0 - RATE_GAIN).powi(intervals);
+        self.rate * keep + sample * (1.0 - keep)
+    }
+}
+
+/// Estimator fed by the agent send and receive paths.
+#[derive(Default)]
+pub(crate) struct Estimator {
+    send: RateMeter,
+    recv: RateMeter,
+    last_arrival: Option<Instant>,
+    last_interval: Option<Duration>,
+    /// Jitter in seconds
+    jitter: f64,
+}
+
+impl Estimator {
+    pub(crate) fn on_send(&mut self, len: usize, now: Instant) {
+        self.send.on_packet(len, now);
+    }
+
+    /// Account received packet, returns true if receive rate was updated
+    pub(crate) fn on_recv(&mut self, len: usize, now: Instant) -> bool {
+        let sampled = self.recv.on_packet(len, now);
+
+        if let Some(last) = self.last_arrival {
+            let interval = now.saturating_duration_since(last);
+            if let Some(prev) = self.last_interval {
+                let variation = (interval.as_secs_f64() - prev.as_secs_f64()).abs();
+                self.jitter += (variation - self.jitter) / JITTER_DIVISOR;
+            }
+            self.last_interval = Some(interval);
+        }
+        self.last_arrival = Some(now);
+        sampled
+    }
+
+    pub(crate) fn estimate(&self, now: Instant) -> BandwidthEstimate {
+        BandwidthEstimate {
+            send_rate: self.send.rate(now),
+            recv_rate: self.recv.rate(now),
+            jitter: Duration::from_secs_f64(self.jitter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_rate() {
+        let mut estimator = Estimator::default();
+        let start = Instant::now();
+
+        // 1000 bytes every 10ms == 800 kbit/s, no jitter
+        for i in 0..500 {
+            let now = start + Duration::from_millis(10 * i);
+            estimator.on_send(1000, now);
+            estimator.on_recv(1000, now);
+        }
+
+        let last = start + Duration::from_millis(10 * 499);
+        let estimate = estimator.estimate(last);
+        assert!((estimate.send_rate - 800_000.0).abs() < 80_000.0);
+        assert!((estimate.recv_rate - 800_000.0).abs() < 80_000.0);
+        assert_eq!(estimate.jitter, Duration::ZERO);
+    }
+
+    #[test]
+    fn decay() {
+        let mut estimator = Estimator::default();
+        let start = Instant::now();
+        for i in 0..500 {
+            estimator.on_recv(1000, start + Duration::from_millis(10 * i));
+        }
+
+        let last = start + Duration::from_millis(10 * 499);
+        let rate = estimator.estimate(last).recv_rate;
+        let after = |ms| {
+            estimator
+                .estimate(last + Duration::from_millis(ms))
+                .recv_rate
+        };
+        assert!(after(500) < rate * 0.5, "{} {}", after(500), rate);
+        assert!(after(3000) < rate * 0.05, "{} {}", after(3000), rate);
+    }
+
+    #[test]
+    fn jitter() {
+        let mut estimator = Estimator::default();
+        let mut now = Instant::now();
+
+        // intervals alternate between 10ms and 30ms
+        let mut samples = 0;
+        for i in 0..500 {
+            now += Duration::from_millis(if i % 2 == 0 { 10 } else { 30 });
+            if estimator.on_recv(100, now) {
+                samples += 1;
+            }
+        }
+        // 10s of packets, sampled once 100ms passed, i.e. every 100-120ms
+        assert!((80..=100).contains(&samples), "{}", samples);
+
+        let jitter = estimator.estimate(now).jitter;
+        assert!(jitter > Duration::from_millis(15), "{:?}", jitter);
+        assert!(jitter <= Duration::from_millis(20), "{:?}", jitter);
+    }
+}
@@ -0,0 +1,264 @@
+//! ICE candidates.
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// Candidate type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CandidateType {
+    Host,
+    ServerReflexive,
+    PeerReflexive,
+    Relayed,
+}
+
+impl FromStr for CandidateType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "host" => CandidateType::Host,
+            "srflx" => CandidateType::ServerReflexive,
+            "prflx" => CandidateType::PeerReflexive,
+            "relay" => CandidateType::Relayed,
+            _ => return Err(Error::InvalidArgument),
+        })
+    }
+}
+
+impl Display for CandidateType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CandidateType::Host => write!(f, "host"),
+            CandidateType::ServerReflexive => write!(f, "srflx"),
+            CandidateType::PeerReflexive => write!(f, "prflx"),
+            CandidateType::Relayed => write!(f, "relay"),
+        }
+    }
+}
+
+/// Parsed ICE candidate.
+///
+/// Keeps the original sdp line, which is returned by [`Display`] implementation and can be
+/// passed as is to [`crate::Agent::add_remote_candidate`].
+///
+/// # Example
+/// ```
+/// # use libjuice_rs::{Candidate, CandidateType};
+/// let c: Candidate = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(c.kind(), CandidateType::Host);
+/// assert_eq!(c.addr(), Some("192.168.1.5:54321".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub struct Candidate {
+    sdp: String,
+    foundation: String,
+    component: u32,
+    transport: String,
+    priority: u32,
+    address: String,
+    port: u16,
+    kind: CandidateType,
+}
+
+impl Candidate {
+    /// Get foundation
+    pub fn foundation(&self) -> &str {
+        &self.foundation
+    }
+
+    /// Get component id
+    pub fn component(&self) -> u32 {
+        self.component
+    }
+
+    /// Get transport, e.g. "UDP"
+    pub fn transport(&self) -> &str {
+        &self.transport
+    }
+
+    /// Get priority
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// Get connection address, either IP address or hostname
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Get port
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get socket address, `None` if connection address is a hostname
+    pub fn addr(&self) -> Option<SocketAddr> {
+        let ip = self.address.parse::<IpAddr>().ok()?;
+        Some(SocketAddr::new(ip, self.port))
+    }
+
+    /// Get candidate type
+    pub fn kind(&self) -> CandidateType {
+        self.kind
+    }
+
+    /// Get sdp line
+    pub fn as_sdp(&self) -> &str {
+        &self.sdp
+    }
+
+    /// Copy with given priority, sdp line updated accordingly
+    pub(crate) fn with_priority(&self, priority: u32) -> Self {
+        let (prefix, value) = match self.sdp.strip_prefix("a=") {
+            Some(value) => ("a=", value),
+            None => ("", self.sdp.as_str()),
+        };
+        let priority_str = priority.to_string();
+        let mut tokens = value.split_ascii_whitespace().collect::<Vec<_>>();
+        tokens[3] = &priority_str; // parsed already
+        Self {
+            sdp: format!("{}{}", prefix, tokens.join(" ")),
+            priority,
+            ..self.clone()
+        }
+    }
+}
+
+impl FromStr for Candidate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let sdp = s.trim();
+        let value = sdp.strip_prefix("a=").unwrap_or(sdp);
+        let value = value
+            .strip_prefix("candidate:")
+            .ok_or(Error::InvalidArgument)?;
+
+        let mut tokens = value.split_ascii_whitespace();
+        let mut next = || tokens.next().ok_or(Error::InvalidArgument);
+
+        let foundation = next()?.to_string();
+        let component = next()?.parse().map_err(|_| Error::InvalidArgument)?;
+        let transport = next()?.to_string();
+        let priority = next()?.parse().map_err(|_| Error::InvalidArgument)?;
+        let address = next()?.to_string();
+        let port = next()?.parse().map_err(|_| Error::InvalidArgument)?;
+        if next()? != "typ" {
+            return Err(Error::InvalidArgument);
+        }
+        let kind = next()?.parse()?;
+
+        Ok(Self {
+            sdp: sdp.to_string(),
+            foundation,
+            component,
+            transport,
+            priority,
+            address,
+            port,
+            kind,
+        })
+    }
+}
+
+impl TryFrom<String> for Candidate {
+    type Error = Error;
+
+    fn try_from(sdp: String) -> Result<Self> {
+        sdp.parse()
+    }
+}
+
+impl From<Candidate> for String {
+    fn from(candidate: Candidate) -> Self {
+        candidate.sdp
+    }
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.sdp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let sdp = "a=candidate:2 1 UDP 1686052863 203.0.113.7 5000 typ srflx raddr 0.0.0.0 rport 0";
+        let c: Candidate = sdp.parse().unwrap();
+        assert_eq!(c.foundation(), "2");
+        assert_eq!(c.component(), 1);
+        assert_eq!(c.transport(), "UDP");
+        assert_eq!(c.priority(), 1686052863);
+        assert_eq!(c.addr(), Some("203.0.113.7:5000".parse().unwrap()));
+        assert_eq!(c.kind(), CandidateType::ServerReflexive);
+        assert_eq!(c.to_string(), sdp);
+
+        let c: Candidate = "candidate:1 1 UDP 2122317823 fe80::1 6000 typ host"
+            .parse()
+            .unwrap();
+        assert_eq!(c.addr(), Some("[fe80::1]:6000".parse().unwrap()));
+
+        let c: Candidate = "candidate:1 1 UDP 2122317823 host.local 6000 typ host"
+            .parse()
+            .unwrap();
+        assert_eq!(c.address(), "host.local");
+        assert_eq!(c.addr(), None);
+    }
+
+    #[test]
+    fn priority() {
+        let c: Candidate = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host"
+            .parse()
+            .unwrap();
+        let c = c.with_priority(100);
+        assert_eq!(c.priority(), 100);
+        assert_eq!(
+            c.as_sdp(),
+            "a=candidate:1 1 UDP 100 192.168.1.5 54321 typ host"
+        );
+        assert_eq!(c.to_string().parse::<Candidate>(), Ok(c));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let sdp = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host";
+        let json = serde_json::to_string(&sdp.parse::<Candidate>().unwrap()).unwrap();
+        assert_eq!(json, format!("{:?}", sdp));
+        let c: Candidate = serde_json::from_str(&json).unwrap();
+        assert_eq!(c.addr(), Some("192.168.1.5:54321".parse().unwrap()));
+
+        assert!(serde_json::from_str::<Candidate>(r#""a=ice-ufrag:abcd""#).is_err());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for sdp in [
+            "",
+            "a=candidate:1 1 UDP",
+            "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 host",
+            "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ unknown",
+            "a=candidate:1 1 UDP priority 192.168.1.5 54321 typ host",
+            "a=ice-ufrag:abcd",
+        ] {
+            assert_eq!(
+                sdp.parse::<Candidate>(),
+                Err(Error::InvalidArgument),
+                "{}",
+                sdp
+            );
+        }
+    }
+}
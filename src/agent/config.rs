@@ -0,0 +1,162 @@
+//! Declarative agent configuration.
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::agent::{Builder, ConcurrencyMode, StunServer};
+use crate::{Handler, Result};
+
+/// STUN server address.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StunServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+/// TURN server address and credentials.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TurnServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+}
+
+/// Agent configuration, suitable to be loaded from configuration files.
+///
+/// Options taking callbacks, like [`Builder::with_reconnect_policy`] and
+/// [`Builder::with_relay_fallback`], are set on the builder returned by [`Builder::from_config`].
+/// libjuice doesn't expose its connectivity check and consent freshness timeouts, so these are
+/// not configurable.
+///
+/// # Example
+/// ```
+/// # use libjuice_rs::{AgentConfig, Builder, Handler, StunServerConfig};
+/// let config = AgentConfig {
+///     stun_server: Some(StunServerConfig {
+///         host: "stun.example.com".into(),
+///         port: 3478,
+///     }),
+///     port_range: Some((5000, 5100)),
+///     ..Default::default()
+/// };
+/// let builder = Builder::from_config(config, Handler::default()).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AgentConfig {
+    /// Concurrency mode, default is [`ConcurrencyMode::Poll`]
+    pub concurrency_mode: Option<ConcurrencyMode>,
+    /// STUN server, default is "stun.l.google.com:19302"
+    pub stun_server: Option<StunServerConfig>,
+    /// Don't use STUN server, see [`Builder::without_stun`]
+    pub no_stun: bool,
+    /// TURN servers
+    pub turn_servers: Vec<TurnServerConfig>,
+    /// Local port range
+    pub port_range: Option<(u16, u16)>,
+    /// Address to bind to
+    pub bind_address: Option<IpAddr>,
+    /// Local candidates batching window in milliseconds, see [`Builder::with_trickle_batching`]
+    pub trickle_batching_ms: Option<u64>,
+    /// Gathering timeout in milliseconds, see [`Builder::with_gathering_timeout`], stalled
+    /// servers are only logged
+    pub gathering_timeout_ms: Option<u64>,
+}
+
+impl Builder {
+    /// Create builder from configuration with given handler.
+    pub fn from_config(config: AgentConfig, handler: Handler) -> Result<Self> {
+        let mut builder = Builder::new(handler);
+
+        if let Some(mode) = config.concurrency_mode {
+            builder = builder.with_concurrency_mode(mode);
+        }
+        if let Some(stun) = config.stun_server {
+            builder.stun_server = Some(StunServer::new(stun.host, stun.port)?);
+        }
+        if config.no_stun {
+            builder = builder.without_stun();
+        }
+        for turn in config.turn_servers {
+            builder =
+                builder.add_turn_server(turn.host, turn.port, turn.username, turn.password)?;
+        }
+        if let Some((begin, end)) = config.port_range {
+            builder = builder.with_port_range(begin, end);
+        }
+        if let Some(addr) = config.bind_address {
+            builder = builder.with_bind_address(&addr);
+        }
+        if let Some(window) = config.trickle_batching_ms {
+            builder = builder.with_trickle_batching(Duration::from_millis(window));
+        }
+        if let Some(timeout) = config.gathering_timeout_ms {
+            builder = builder.with_gathering_timeout(Duration::from_millis(timeout), |_| {});
+        }
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn from_config() {
+        let config = AgentConfig {
+            turn_servers: vec![TurnServerConfig {
+                host: "turn.example.com".into(),
+                port: 3478,
+                username: "user".into(),
+                password: "pass".into(),
+            }],
+            bind_address: Some("127.0.0.1".parse().unwrap()),
+            trickle_batching_ms: Some(100),
+            no_stun: true,
+            gathering_timeout_ms: Some(2000),
+            ..Default::default()
+        };
+        let builder = Builder::from_config(config, Handler::default()).unwrap();
+        assert_eq!(builder.turn_servers.len(), 1);
+        assert_eq!(builder.trickle_batching, Some(Duration::from_millis(100)));
+        assert!(builder.no_stun);
+        assert!(matches!(
+            builder.gathering_timeout,
+            Some((timeout, _)) if timeout == Duration::from_secs(2)
+        ));
+
+        let config = AgentConfig {
+            stun_server: Some(StunServerConfig {
+                host: "stun\0".into(),
+                port: 3478,
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Builder::from_config(config, Handler::default()),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize() {
+        let config: AgentConfig = serde_json::from_str(
+            r#"{
+                "stun_server": {"host": "stun.example.com", "port": 3478},
+                "port_range": [5000, 5100],
+                "concurrency_mode": "Mux"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.stun_server.unwrap().host, "stun.example.com");
+        assert_eq!(config.port_range, Some((5000, 5100)));
+        assert_eq!(config.concurrency_mode, Some(ConcurrencyMode::Mux));
+        assert!(config.turn_servers.is_empty());
+    }
+}
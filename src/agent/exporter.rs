@@ -0,0 +1,169 @@
+//! Agent metrics export through the `metrics` facade.
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use metrics::{counter, describe_counter, describe_gauge, gauge, Label, Unit};
+
+use crate::agent::candidate::CandidateType;
+use crate::agent::stats::Stats;
+use crate::agent::{Holder, State};
+use crate::clock::{self, Clock};
+
+/// Publish agent metrics every `interval` until the agent is dropped
+pub(crate) fn export(
+    holder: Weak<Holder>,
+    clock: Arc<dyn Clock>,
+    interval: Duration,
+    labels: Vec<Label>,
+    alive: Receiver<()>,
+) {
+    describe();
+    while let Err(RecvTimeoutError::Timeout) = clock::recv_timeout(&*clock, &alive, interval) {
+        let holder = match holder.upgrade() {
+            Some(holder) => holder,
+            None => break,
+        };
+        let pair = holder.selected_pair().ok().map(|(_, _, l, r)| (l, r));
+        publish(&labels, holder.state(), holder.counters.snapshot(), pair);
+    }
+}
+
+fn describe() {
+    describe_gauge!(
+        "juice_agent_state",
+        "ICE state: 0 disconnected, 1 gathering, 2 connecting, 3 connected, 4 completed, 5 failed"
+    );
+    describe_gauge!(
+        "juice_selected_pair_type",
+        "Candidate type of the selected pair side: 0 host, 1 srflx, 2 prflx, 3 relay, -1 none"
+    );
+    describe_counter!("juice_bytes_sent_total", Unit::Bytes, "Bytes sent");
+    describe_counter!("juice_bytes_received_total", Unit::Bytes, "Bytes received");
+    describe_counter!("juice_packets_sent_total", Unit::Count, "Packets sent");
+    describe_counter!(
+        "juice_packets_received_total",
+        Unit::Count,
+        "Packets received"
+    );
+}
+
+fn publish(
+    labels: &[Label],
+    state: State,
+    stats: Stats,
+    pair: Option<(CandidateType, CandidateType)>,
+) {
+    let state = match state {
+        State::Disconnected => 0.0,
+        State::Gathering => 1.0,
+        State::Connecting => 2.0,
+        State::Connected => 3.0,
+        State::Completed => 4.0,
+        State::Failed => 5.0,
+    };
+    gauge!("juice_agent_state", labels.iter()).set(state);
+
+    counter!("juice_bytes_sent_total", labels.iter()).absolute(stats.bytes_sent);
+    counter!("juice_bytes_received_total", labels.iter()).absolute(stats.bytes_received);
+    counter!("juice_packets_sent_total", labels.iter()).absolute(stats.packets_sent);
+    counter!("juice_packets_received_total", labels.iter()).absolute(stats.packets_received);
+
+    let kind = |kind: CandidateType| match kind {
+        CandidateType::Host => 0.0,
+        CandidateType::ServerReflexive => 1.0,
+        CandidateType::PeerReflexive => 2.0,
+        CandidateType::Relayed => 3.0,
+    };
+    let (local, remote) = match pair {
+        Some((local, remote)) => (kind(local), kind(remote)),
+        None => (-1.0, -1.0),
+    };
+    for (side, value) in [("local", local), ("remote", remote)] {
+        let mut labels = labels.to_vec();
+        labels.push(Label::new("side", side));
+        gauge!("juice_selected_pair_type", labels).set(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+
+    /// Keeps raw values by name and labels
+    #[derive(Default)]
+    struct TestRecorder(Mutex<BTreeMap<String, Arc<AtomicU64>>>);
+
+    impl TestRecorder {
+        fn register(&self, key: &Key) -> Arc<AtomicU64> {
+            let labels = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect::<Vec<_>>();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            self.0.lock().unwrap().entry(name).or_default().clone()
+        }
+
+        fn get(&self, name: &str) -> u64 {
+            self.0.lock().unwrap()[name].load(Ordering::Relaxed)
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.register(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.register(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn publish_stats() {
+        let recorder = TestRecorder::default();
+        let labels = vec![Label::new("agent", "a1")];
+        let stats = Stats {
+            packets_sent: 2,
+            bytes_sent: 300,
+            packets_received: 1,
+            bytes_received: 100,
+        };
+
+        metrics::with_local_recorder(&recorder, || {
+            publish(
+                &labels,
+                State::Connected,
+                stats,
+                Some((CandidateType::Relayed, CandidateType::Host)),
+            );
+        });
+
+        let gauge = |name| f64::from_bits(recorder.get(name));
+        assert_eq!(gauge("juice_agent_state{agent=a1}"), 3.0);
+        assert_eq!(recorder.get("juice_bytes_sent_total{agent=a1}"), 300);
+        assert_eq!(recorder.get("juice_packets_received_total{agent=a1}"), 1);
+        assert_eq!(gauge("juice_selected_pair_type{agent=a1,side=local}"), 3.0);
+        assert_eq!(gauge("juice_selected_pair_type{agent=a1,side=remote}"), 0.0);
+
+        metrics::with_local_recorder(&recorder, || {
+            publish(&labels, State::Failed, stats, None);
+        });
+        assert_eq!(gauge("juice_agent_state{agent=a1}"), 5.0);
+        assert_eq!(gauge("juice_selected_pair_type{agent=a1,side=local}"), -1.0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Gathering timeout with partial results.
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use crate::agent::candidate::{Candidate, CandidateType};
+use crate::agent::Holder;
+use crate::clock::{self, Clock};
+
+/// STUN or TURN servers which produced no candidate before the gathering timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StalledServer {
+    /// No server reflexive candidate, also reported if the reflexive address equals a host one
+    Stun { host: String, port: u16 },
+    /// Fewer relayed candidates than TURN servers.
+    ///
+    /// libjuice doesn't tell which server allocated a relay, relay addresses don't have to be on
+    /// the server address either, so only the number of missing allocations is known.
+    Turn { configured: usize, allocated: usize },
+}
+
+/// Invoked with the stalled servers when gathering times out
+pub(crate) type TimeoutHandler = Box<dyn FnMut(Vec<StalledServer>) + Send + 'static>;
+
+#[derive(Default)]
+struct Progress {
+    /// Incremented on every gathering start
+    generation: u64,
+    /// Gathering done reported, by libjuice or the timeout
+    done: bool,
+    srflx: bool,
+    relays: usize,
+}
+
+/// Gathering progress of the agent.
+pub(crate) struct Gathering {
+    started: Mutex<Sender<u64>>,
+    progress: Mutex<Progress>,
+    on_timeout: Mutex<TimeoutHandler>,
+}
+
+impl Gathering {
+    pub(crate) fn new(started: Sender<u64>, on_timeout: TimeoutHandler) -> Self {
+        Self {
+            started: Mutex::new(started),
+            progress: Mutex::new(Progress::default()),
+            on_timeout: Mutex::new(on_timeout),
+        }
+    }
+
+    /// Start timing new gathering
+    pub(crate) fn start(&self) {
+        let mut progress = self.progress.lock().unwrap();
+        *progress = Progress {
+            generation: progress.generation + 1,
+            ..Progress::default()
+        };
+        let _ = self.started.lock().unwrap().send(progress.generation);
+    }
+
+    /// Record local candidate, false if gathering was finished by the timeout already
+    pub(crate) fn on_candidate(&self, candidate: &Candidate) -> bool {
+        let mut progress = self.progress.lock().unwrap();
+        match candidate.kind() {
+            CandidateType::ServerReflexive => progress.srflx = true,
+            CandidateType::Relayed => progress.relays += 1,
+            _ => {}
+        }
+        !progress.done
+    }
+
+    /// Mark gathering done, false if it was reported already
+    pub(crate) fn on_gathering_done(&self) -> bool {
+        !std::mem::replace(&mut self.progress.lock().unwrap().done, true)
+    }
+
+    /// Finish gathering `generation` unless done, returns whether srflx candidate was gathered
+    /// and the number of relayed ones
+    fn expire(&self, generation: u64) -> Option<(bool, usize)> {
+        let mut progress = self.progress.lock().unwrap();
+        if progress.generation != generation || progress.done {
+            return None;
+        }
+        progress.done = true;
+        Some((progress.srflx, progress.relays))
+    }
+}
+
+/// Timer loop, lives until the agent is dropped.
+///
+/// Reports gathering done `timeout` after the latest gathering start, unless libjuice did it
+/// before.
+pub(crate) fn watch(
+    holder: Weak<Holder>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+    started: Receiver<u64>,
+) {
+    let mut pending: Option<(u64, Instant)> = None;
+    loop {
+        let event = match pending {
+            Some((_, deadline)) => {
+                let left = deadline.saturating_duration_since(clock.now());
+                clock::recv_timeout(&*clock, &started, left)
+            }
+            None => started.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let generation = match event {
+            Ok(generation) => {
+                pending = Some((generation, clock.now() + timeout));
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => match pending.take() {
+                Some((generation, _)) => generation,
+                None => continue,
+            },
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        let holder = match holder.upgrade() {
+            Some(holder) => holder,
+            None => break,
+        };
+        let gathering = holder.gathering.as_ref().expect("gathering timeout is set");
+        if let Some((srflx, relays)) = gathering.expire(generation) {
+            let stalled = stalled_servers(&holder, srflx, relays);
+            log::warn!(
+                "{}gathering timed out, stalled servers: {:?}",
+                holder.label(),
+                stalled
+            );
+            (gathering.on_timeout.lock().unwrap())(stalled);
+            holder.finish_gathering();
+        }
+    }
+}
+
+/// Servers which produced no candidate so far
+fn stalled_servers(holder: &Holder, srflx: bool, relays: usize) -> Vec<StalledServer> {
+    let config = &holder.config;
+    let mut stalled = vec![];
+    if let (Some(server), false) = (&config.stun_server, srflx) {
+        stalled.push(StalledServer::Stun {
+            host: server.0.to_string_lossy().into_owned(),
+            port: server.1,
+        });
+    }
+    let configured = config.turn_servers.len();
+    if relays < configured {
+        stalled.push(StalledServer::Turn {
+            configured,
+            allocated: relays,
+        });
+    }
+    stalled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn generations() {
+        let (tx, rx) = channel();
+        let gathering = Gathering::new(tx, Box::new(|_| {}));
+        let srflx: Candidate = "a=candidate:2 1 UDP 1686052863 203.0.113.7 5000 typ srflx"
+            .parse()
+            .unwrap();
+        let relay: Candidate = "a=candidate:3 1 UDP 16777215 198.51.100.1 6000 typ relay"
+            .parse()
+            .unwrap();
+
+        gathering.start();
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(gathering.on_candidate(&srflx));
+        assert!(gathering.on_candidate(&relay));
+        assert_eq!(gathering.expire(1), Some((true, 1)));
+        // late candidates and libjuice gathering done are suppressed
+        assert!(!gathering.on_candidate(&srflx));
+        assert!(!gathering.on_gathering_done());
+
+        // restarted before the timer of the previous gathering fired
+        gathering.start();
+        gathering.start();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(gathering.expire(2), None);
+        assert!(gathering.on_gathering_done());
+        assert_eq!(gathering.expire(3), None);
+    }
+}
@@ -1,5 +1,29 @@
+use std::net::SocketAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+use crate::agent::bandwidth::BandwidthEstimate;
+use crate::agent::candidate::Candidate;
 use crate::agent::State;
 
+/// Incoming packet metadata.
+///
+/// libjuice doesn't report the source of each packet, so the path fields describe the selected
+/// pair at arrival time, which usually but not necessarily carried the packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RecvMeta {
+    /// Packet arrival time, taken as soon as libjuice hands the packet over
+    pub timestamp: Instant,
+    /// Whether local candidate of the selected pair is a relayed one
+    pub via_relay: bool,
+    /// Remote address of the selected pair, unspecified if it is not known yet
+    pub remote: SocketAddr,
+}
+
+type RecvHandler = Box<dyn FnMut(&[u8]) + Send + 'static>;
+type RecvMetaHandler = Box<dyn FnMut(&[u8], RecvMeta) + Send + 'static>;
+type RecvFilter = Box<dyn FnMut(&[u8]) -> bool + Send + 'static>;
+
 /// Closures based event handler.
 ///
 /// Any closure from given handler can be invoked in any thread, usually from dedicated internal
@@ -20,10 +44,20 @@ pub struct Handler {
     on_state_change: Option<Box<dyn FnMut(State) + Send + 'static>>,
     /// Local ICE candidate handler
     on_candidate: Option<Box<dyn FnMut(String) + Send + 'static>>,
+    /// Local ICE candidates batch handler
+    on_candidates: Option<Box<dyn FnMut(Vec<Candidate>) + Send + 'static>>,
     /// Gathering stage finish handler
     on_gathering_done: Option<Box<dyn FnMut() + Send + 'static>>,
     /// Incoming packet
-    on_recv: Option<Box<dyn FnMut(&[u8]) + Send + 'static>>,
+    on_recv: Option<RecvHandler>,
+    /// Incoming packet with metadata
+    on_recv_meta: Option<RecvMetaHandler>,
+    /// TURN allocation success
+    on_relay_ready: Option<Box<dyn FnMut(SocketAddr) + Send + 'static>>,
+    /// Receive side bandwidth estimate update
+    on_bandwidth: Option<Box<dyn FnMut(BandwidthEstimate) + Send + 'static>>,
+    /// Internal consumers of incoming packets, consumed packets skip recv handlers
+    recv_filter: Option<RecvFilter>,
 }
 
 impl Handler {
@@ -31,7 +65,7 @@ impl Handler {
     pub fn state_handler<F>(mut self, f: F) -> Self
     where
         F: FnMut(State),
-        F: Send + Sync + 'static,
+        F: Send + 'static,
     {
         self.on_state_change = Some(Box::new(f));
         self
@@ -47,6 +81,19 @@ impl Handler {
         self
     }
 
+    /// Set local candidates batch handler.
+    ///
+    /// Candidates are batched when [`crate::Builder::with_trickle_batching`] is set, otherwise
+    /// each batch consists of a single candidate.
+    pub fn candidates_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Vec<Candidate>),
+        F: Send + 'static,
+    {
+        self.on_candidates = Some(Box::new(f));
+        self
+    }
+
     /// Set gathering done handler
     pub fn gathering_done_handler<F>(mut self, f: F) -> Self
     where
@@ -57,6 +104,32 @@ impl Handler {
         self
     }
 
+    /// Set gathering done handler invoked at most once, e.g. to send a channel `Sender` or a
+    /// oneshot completion
+    pub fn gathering_done_once<F>(self, f: F) -> Self
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        let mut f = Some(f);
+        self.gathering_done_handler(move || {
+            if let Some(f) = f.take() {
+                f()
+            }
+        })
+    }
+
+    /// Set handler invoked with the relayed address whenever a TURN allocation succeeds, i.e.
+    /// a local relayed candidate is gathered
+    pub fn relay_ready_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(SocketAddr),
+        F: Send + 'static,
+    {
+        self.on_relay_ready = Some(Box::new(f));
+        self
+    }
+
     /// Set incoming packet handler
     pub fn recv_handler<F>(mut self, f: F) -> Self
     where
@@ -67,6 +140,77 @@ impl Handler {
         self
     }
 
+    /// Set incoming packet handler receiving [`RecvMeta`] along with the payload.
+    ///
+    /// Invoked in addition to [`Handler::recv_handler`] if both are set.
+    pub fn recv_handler_with_meta<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&[u8], RecvMeta),
+        F: Send + 'static,
+    {
+        self.on_recv_meta = Some(Box::new(f));
+        self
+    }
+
+    /// Set bandwidth estimate handler, invoked whenever receive rate estimate is updated.
+    ///
+    /// Setting it enables [`crate::Builder::with_bandwidth_estimation`].
+    pub fn bandwidth_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(BandwidthEstimate),
+        F: Send + 'static,
+    {
+        self.on_bandwidth = Some(Box::new(f));
+        self
+    }
+
+    pub(crate) fn has_bandwidth_handler(&self) -> bool {
+        self.on_bandwidth.is_some()
+    }
+
+    /// Invoke `hook` after the gathering done handler
+    pub(crate) fn chain_gathering_done<F>(mut self, mut hook: F) -> Self
+    where
+        F: FnMut(),
+        F: Send + 'static,
+    {
+        let mut prev = self.on_gathering_done.take();
+        self.on_gathering_done = Some(Box::new(move || {
+            if let Some(f) = &mut prev {
+                f()
+            }
+            hook()
+        }));
+        self
+    }
+
+    /// Pass incoming packets to `filter` first, packets it returns true for are consumed
+    pub(crate) fn intercept_recv<F>(mut self, mut filter: F) -> Self
+    where
+        F: FnMut(&[u8]) -> bool,
+        F: Send + 'static,
+    {
+        let mut prev = self.recv_filter.take();
+        self.recv_filter = Some(Box::new(move |packet| {
+            prev.as_mut().is_some_and(|f| f(packet)) || filter(packet)
+        }));
+        self
+    }
+
+    /// Replace handler, internal packet consumers of the current one are kept
+    pub(crate) fn replace(&mut self, handler: Handler) {
+        let prev = std::mem::replace(self, handler);
+        if let Some(filter) = prev.recv_filter {
+            *self = std::mem::take(self).intercept_recv(filter);
+        }
+    }
+
+    /// Remove incoming packet handlers
+    pub(crate) fn clear_recv(&mut self) {
+        self.on_recv = None;
+        self.on_recv_meta = None;
+    }
+
     pub(crate) fn on_state_changed(&mut self, state: State) {
         if let Some(f) = &mut self.on_state_change {
             f(state)
@@ -79,15 +223,162 @@ impl Handler {
         }
     }
 
+    pub(crate) fn on_candidates(&mut self, candidates: Vec<Candidate>) {
+        if let Some(f) = &mut self.on_candidates {
+            f(candidates)
+        }
+    }
+
     pub(crate) fn on_gathering_done(&mut self) {
         if let Some(f) = &mut self.on_gathering_done {
             f()
         }
     }
 
-    pub(crate) fn on_recv(&mut self, packet: &[u8]) {
+    pub(crate) fn on_relay_ready(&mut self, addr: SocketAddr) {
+        if let Some(f) = &mut self.on_relay_ready {
+            f(addr)
+        }
+    }
+
+    pub(crate) fn on_bandwidth(&mut self, estimate: BandwidthEstimate) {
+        if let Some(f) = &mut self.on_bandwidth {
+            f(estimate)
+        }
+    }
+
+    pub(crate) fn on_recv<M>(&mut self, packet: &[u8], meta: M)
+    where
+        M: FnOnce() -> RecvMeta,
+    {
+        if let Some(f) = &mut self.recv_filter {
+            if f(packet) {
+                return;
+            }
+        }
         if let Some(f) = &mut self.on_recv {
             f(packet)
         }
+        if let Some(f) = &mut self.on_recv_meta {
+            f(packet, meta())
+        }
+    }
+}
+
+type Update = Box<dyn FnOnce(&mut Handler) + Send + 'static>;
+
+/// Handler modifiable at runtime, including from its own callbacks.
+///
+/// Updates are applied at once if the handler is idle, otherwise before the next event.
+pub(crate) struct HandlerCell {
+    handler: Mutex<Handler>,
+    pending: Mutex<Vec<Update>>,
+}
+
+impl HandlerCell {
+    pub(crate) fn new(handler: Handler) -> Self {
+        Self {
+            handler: Mutex::new(handler),
+            pending: Mutex::new(vec![]),
+        }
+    }
+
+    /// Lock handler to dispatch an event
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Handler> {
+        let mut h = self.handler.lock().unwrap();
+        Self::apply(&mut h, &self.pending);
+        h
+    }
+
+    /// Queue handler update
+    pub(crate) fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Handler),
+        F: Send + 'static,
+    {
+        self.pending.lock().unwrap().push(Box::new(f));
+        // busy means an event is being dispatched, possibly by this very thread
+        if let Ok(mut h) = self.handler.try_lock() {
+            Self::apply(&mut h, &self.pending);
+        }
+    }
+
+    fn apply(h: &mut Handler, pending: &Mutex<Vec<Update>>) {
+        let updates = std::mem::take(&mut *pending.lock().unwrap());
+        for update in updates {
+            update(h)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, OnceLock};
+
+    #[test]
+    fn non_sync_handlers() {
+        // Cell is Send but not Sync
+        let count = Cell::new(0);
+        let (tx, rx) = channel();
+        let mut h = Handler::default()
+            .state_handler(move |_| count.set(count.get() + 1))
+            .gathering_done_once(move || tx.send(()).unwrap());
+
+        h.on_state_changed(State::Gathering);
+        h.on_gathering_done();
+        h.on_gathering_done();
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn replace() {
+        let (tx, rx) = channel();
+        let cell = Arc::new(HandlerCell::new(
+            Handler::default().intercept_recv(|packet| packet == b"internal"),
+        ));
+
+        // switch to the data phase handler from the handshake one
+        let weak = Arc::downgrade(&cell);
+        let data_tx = tx.clone();
+        cell.update(move |h| {
+            h.replace(Handler::default().recv_handler(move |packet| {
+                tx.send(("handshake", packet.to_vec())).unwrap();
+                let data_tx = data_tx.clone();
+                weak.upgrade().unwrap().update(move |h| {
+                    h.replace(Handler::default().recv_handler(move |packet| {
+                        data_tx.send(("data", packet.to_vec())).unwrap();
+                    }))
+                });
+            }))
+        });
+
+        let meta = || RecvMeta {
+            timestamp: Instant::now(),
+            via_relay: false,
+            remote: SocketAddr::from(([0, 0, 0, 0], 0)),
+        };
+        for packet in [&b"hello"[..], b"internal", b"data"] {
+            cell.lock().on_recv(packet, meta);
+        }
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![("handshake", b"hello".to_vec()), ("data", b"data".to_vec())]
+        );
+
+        let received = Arc::new(OnceLock::new());
+        cell.update({
+            let received = received.clone();
+            |h| {
+                h.replace(Handler::default().recv_handler(move |p| {
+                    received.set(p.to_vec()).unwrap();
+                }));
+                h.clear_recv();
+            }
+        });
+        cell.lock().on_recv(b"dropped", meta);
+        assert!(received.get().is_none());
     }
 }
@@ -1,19 +1,60 @@
 //! ICE Agent.
 
+pub mod bandwidth;
+pub mod candidate;
+pub mod config;
+#[cfg(feature = "metrics")]
+mod exporter;
+pub mod gathering;
 pub mod handler;
+pub mod negotiation;
+mod pacer;
+pub mod policy;
+pub mod reconnect;
+pub mod scheduling;
+pub(crate) mod sdp;
+pub mod stats;
+pub mod subchannel;
+pub mod tagged;
+mod trickle;
+mod watchdog;
 
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::ffi::{CStr, CString};
+use std::fmt::Debug;
 use std::marker::PhantomData;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::Deref;
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use bandwidth::{BandwidthEstimate, Estimator};
+use candidate::{Candidate, CandidateType};
+use gathering::{Gathering, StalledServer, TimeoutHandler};
 pub use handler::Handler;
+use handler::{HandlerCell, RecvMeta};
 use libjuice_sys as sys;
+use negotiation::NegotiationState;
+use pacer::Pacer;
+use policy::AddressPolicy;
+use reconnect::ReconnectPolicy;
+use scheduling::{ThreadOptions, ThreadPriority};
+use sdp::{CandidateKey, DefaultCandidate};
+use stats::{Counters, Stats};
+use subchannel::{Demux, SubChannel};
 
+use crate::build_info::version;
+use crate::clock::{Clock, SystemClock};
 use crate::error::Error;
 use crate::log::ensure_logging;
+use crate::signaling::{self, Signaling, END_OF_CANDIDATES};
 use crate::Result;
 
 /// Convert c function retcode to result
@@ -27,33 +68,110 @@ fn raw_retcode_to_result(retcode: c_int) -> Result<()> {
     }
 }
 
+/// Largest datagram libjuice receives without truncation (its receive buffer size)
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 4096;
+/// Link MTU assumed for the selected path
+const LINK_MTU: usize = 1500;
+/// UDP header size
+const UDP_HEADER_SIZE: usize = 8;
+/// TURN Send indication overhead, covers ChannelData as well
+const TURN_OVERHEAD: usize = 48;
+
+/// Time limit for [`Agent::pair_loopback`] to gather and connect
+const LOOPBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for signaling messages before local events are forwarded by [`Agent::negotiate`]
+const NEGOTIATION_POLL: Duration = Duration::from_millis(20);
+
+/// Parse address as formatted by libjuice, IPv6 address may come without brackets
+pub(crate) fn parse_address(s: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = s.parse() {
+        return Some(addr);
+    }
+    let (ip, port) = s.rsplit_once(':')?;
+    Some(SocketAddr::new(ip.parse().ok()?, port.parse().ok()?))
+}
+
+/// Candidate priority override, see [`Builder::with_priority_rewriter`]
+type PriorityRewriter = Box<dyn Fn(&Candidate) -> u32 + Send + 'static>;
+
 /// Agent builder.
 pub struct Builder {
+    concurrency_mode: ConcurrencyMode,
     stun_server: Option<StunServer>,
+    no_stun: bool,
     port_range: Option<(u16, u16)>,
     bind_address: Option<CString>,
+    mux_socket: Option<SocketAddr>,
     turn_servers: Vec<TurnServer>,
     handler: Handler,
+    reconnect: Option<(ReconnectPolicy, reconnect::RestartHandler)>,
+    trickle_batching: Option<Duration>,
+    relay_fallback: Option<(Duration, Box<dyn FnMut() + Send + 'static>)>,
+    gathering_timeout: Option<(Duration, TimeoutHandler)>,
+    bandwidth_estimation: bool,
+    strict_signaling: bool,
+    default_candidate: DefaultCandidate,
+    normalize_candidates: bool,
+    address_policy: Option<AddressPolicy>,
+    priority_rewriter: Option<PriorityRewriter>,
+    rewrite_remote_priorities: bool,
+    #[cfg(feature = "metrics")]
+    metrics_interval: Option<Duration>,
+    thread_options: ThreadOptions,
+    clock: Arc<dyn Clock>,
+    context: Option<(Arc<dyn Any + Send + Sync>, String)>,
 }
 
 impl Builder {
     /// Create new builder with given handler
     fn new(handler: Handler) -> Self {
         Builder {
+            concurrency_mode: ConcurrencyMode::default(),
             stun_server: None,
+            no_stun: false,
             port_range: None,
             bind_address: None,
+            mux_socket: None,
             turn_servers: vec![],
             handler,
+            reconnect: None,
+            trickle_batching: None,
+            relay_fallback: None,
+            gathering_timeout: None,
+            bandwidth_estimation: false,
+            strict_signaling: false,
+            default_candidate: DefaultCandidate::default(),
+            normalize_candidates: false,
+            address_policy: None,
+            priority_rewriter: None,
+            rewrite_remote_priorities: false,
+            #[cfg(feature = "metrics")]
+            metrics_interval: None,
+            thread_options: ThreadOptions::default(),
+            clock: Arc::new(SystemClock),
+            context: None,
         }
     }
 
+    /// Set concurrency mode (default is [`ConcurrencyMode::Poll`])
+    pub fn with_concurrency_mode(mut self, mode: ConcurrencyMode) -> Self {
+        self.concurrency_mode = mode;
+        self
+    }
+
     /// Set alternative stun server (default is "stun.l.google.com:19302")
     pub fn with_stun(mut self, host: String, port: u16) -> Self {
         self.stun_server = Some(StunServer::new(host, port).unwrap());
         self
     }
 
+    /// Don't use stun server, no server reflexive candidates are gathered
+    pub fn without_stun(mut self) -> Self {
+        self.no_stun = true;
+        self
+    }
+
     /// Set port range
     pub fn with_port_range(mut self, begin: u16, end: u16) -> Self {
         self.port_range = Some((begin, end));
@@ -66,6 +184,33 @@ impl Builder {
         self
     }
 
+    /// Share a single socket bound to `addr` with other agents in [`ConcurrencyMode::Mux`],
+    /// which is set as well.
+    ///
+    /// Port 0 lets the first agent pick one, see [`Agent::mux_port`]. Build fails with
+    /// [`Error::InvalidArgument`] if other concurrency mode, port range or bind address is set.
+    pub fn with_mux_socket(mut self, addr: SocketAddr) -> Self {
+        self.concurrency_mode = ConcurrencyMode::Mux;
+        self.mux_socket = Some(addr);
+        self
+    }
+
+    /// Name libjuice thread running the agent, truncated to 15 bytes on Linux.
+    ///
+    /// See [`crate::scheduling`] for when and to which thread it applies.
+    pub fn with_thread_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.thread_options.name = Some(name.into());
+        self
+    }
+
+    /// Set scheduling priority of libjuice thread running the agent.
+    ///
+    /// See [`crate::scheduling`] for when and to which thread it applies.
+    pub fn with_thread_priority(mut self, priority: ThreadPriority) -> Self {
+        self.thread_options.priority = priority;
+        self
+    }
+
     /// Add TURN server
     pub fn add_turn_server<T>(mut self, host: T, port: u16, user: T, pass: T) -> Result<Self>
     where
@@ -82,71 +227,409 @@ impl Builder {
         Ok(self)
     }
 
-    /// Build agent
+    /// Enable automatic ICE restart on failure.
+    ///
+    /// Once the agent reaches [`State::Failed`] it is recreated according to the given policy.
+    /// Fresh local description is passed to `on_restart` and must be delivered to the remote
+    /// party, new local candidates are reported with the regular candidate handler.
+    /// [`State::Failed`] is reported to the handler only when the policy is exhausted.
+    pub fn with_reconnect_policy<F>(mut self, policy: ReconnectPolicy, on_restart: F) -> Self
+    where
+        F: FnMut(String),
+        F: Send + 'static,
+    {
+        self.reconnect = Some((policy, Box::new(on_restart)));
+        self
+    }
+
+    /// Coalesce local candidates discovered within given window into a single
+    /// [`Handler::candidates_handler`] invocation.
+    pub fn with_trickle_batching(mut self, window: Duration) -> Self {
+        self.trickle_batching = Some(window);
+        self
+    }
+
+    /// Fall back to relay when the direct selected pair silently dies.
+    ///
+    /// If no packet is received on a direct (non-relayed) selected pair for `silence` while the
+    /// application keeps sending and local relayed candidate is available, `on_fallback` is
+    /// invoked. Only application packets count: libjuice consent and keepalive traffic is not
+    /// visible to the wrapper, so an idle path is considered healthy and the option suits
+    /// bidirectional traffic, e.g. media with receiver reports or application keepalives.
+    ///
+    /// Unlike full ICE, the agent doesn't switch to the already validated relayed pair, as libjuice
+    /// doesn't allow to select a pair explicitly. Instead the agent is restarted if
+    /// [`Builder::with_reconnect_policy`] is set, gathering and checks run again and the new
+    /// local description is passed to the restart handler. Otherwise the application is expected
+    /// to react.
+    pub fn with_relay_fallback<F>(mut self, silence: Duration, on_fallback: F) -> Self
+    where
+        F: FnMut(),
+        F: Send + 'static,
+    {
+        self.relay_fallback = Some((silence, Box::new(on_fallback)));
+        self
+    }
+
+    /// Report gathering done `timeout` after gathering start even if some STUN or TURN servers
+    /// didn't respond, local candidates gathered later are not reported.
+    ///
+    /// `on_timeout` receives the servers which produced no candidate, see [`StalledServer`],
+    /// right before the gathering done handler. The timer does no DNS resolution.
+    pub fn with_gathering_timeout<F>(mut self, timeout: Duration, on_timeout: F) -> Self
+    where
+        F: FnMut(Vec<StalledServer>),
+        F: Send + 'static,
+    {
+        self.gathering_timeout = Some((timeout, Box::new(on_timeout)));
+        self
+    }
+
+    /// Estimate achieved throughput and jitter of the data path, see [`Agent::bandwidth_estimate`]
+    pub fn with_bandwidth_estimation(mut self) -> Self {
+        self.bandwidth_estimation = true;
+        self
+    }
+
+    /// Validate remote description before passing it to libjuice.
+    ///
+    /// When enabled, [`Agent::set_remote_description`] fails immediately with
+    /// [`Error::InvalidDescription`] on missing or malformed credentials and unsupported
+    /// candidates instead of failing on connectivity checks timeout.
+    pub fn strict_signaling(mut self, strict: bool) -> Self {
+        self.strict_signaling = strict;
+        self
+    }
+
+    /// Set connection line added to the local description (default is
+    /// [`DefaultCandidate::Omit`])
+    pub fn with_default_candidate(mut self, default: DefaultCandidate) -> Self {
+        self.default_candidate = default;
+        self
+    }
+
+    /// Sort candidates deterministically (by type, address family, then descending priority)
+    /// and drop duplicates in the local description, candidates batches and trickled
+    /// candidates (default is false)
+    pub fn normalize_candidates(mut self, normalize: bool) -> Self {
+        self.normalize_candidates = normalize;
+        self
+    }
+
+    /// Override priorities of local candidates in the description and trickled candidates, e.g.
+    /// to prefer wired interfaces.
+    ///
+    /// Affects the order of the remote agent checks, libjuice keeps its own priorities for local
+    /// candidates. See [`Builder::rewrite_remote_priorities`] to reorder local checks as well.
+    pub fn with_priority_rewriter<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Candidate) -> u32,
+        F: Send + 'static,
+    {
+        self.priority_rewriter = Some(Box::new(f));
+        self
+    }
+
+    /// Apply priority rewriter to remote candidates too (default is false)
+    pub fn rewrite_remote_priorities(mut self, rewrite: bool) -> Self {
+        self.rewrite_remote_priorities = rewrite;
+        self
+    }
+
+    /// Filter remote candidates by address (default is to accept any).
+    ///
+    /// Denied candidates are dropped from the remote description and rejected by
+    /// [`Agent::add_remote_candidate`] with [`Error::AddressRejected`], so are candidates which
+    /// can't be parsed by [`Candidate`]. Use
+    /// [`AddressPolicy::deny_internal`] to keep agents accepting descriptions from untrusted
+    /// peers off the internal network.
+    pub fn with_remote_address_policy(mut self, policy: AddressPolicy) -> Self {
+        self.address_policy = Some(policy);
+        self
+    }
+
+    /// Attach user data to the agent, available with [`Agent::context`].
+    ///
+    /// Its debug representation labels log messages of the agent. Use
+    /// [`crate::TaggedHandler::builder`] to have it passed to every handler invocation as well.
+    pub fn with_context<T>(mut self, context: T) -> Self
+    where
+        T: Debug + Send + Sync + 'static,
+    {
+        let label = format!("{:?}: ", context);
+        self.context = Some((Arc::new(context), label));
+        self
+    }
+
+    /// Publish state, traffic and selected pair type metrics through the `metrics` facade every
+    /// `interval`.
+    ///
+    /// Metrics are labeled with `agent` set to the debug representation of
+    /// [`Builder::with_context`] value if there is one.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, interval: Duration) -> Self {
+        self.metrics_interval = Some(interval);
+        self
+    }
+
+    /// Replace time source of wrapper-side timers
+    #[cfg(test)]
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Invoke `hook` after the gathering done handler
+    #[cfg(feature = "nat-sim")]
+    pub(crate) fn chain_gathering_done<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(),
+        F: Send + 'static,
+    {
+        self.handler = self.handler.chain_gathering_done(hook);
+        self
+    }
+
+    /// Resolve port range and bind address, checking them against the mux socket
+    fn socket_config(&self) -> crate::Result<((u16, u16), Option<CString>)> {
+        let addr = match self.mux_socket {
+            // [0..0] == no range
+            None => return Ok((self.port_range.unwrap_or((0, 0)), self.bind_address.clone())),
+            Some(addr) => addr,
+        };
+        let port_range = (addr.port(), addr.port());
+        let bind_address =
+            (!addr.ip().is_unspecified()).then(|| CString::new(addr.ip().to_string()).unwrap()); // can't fail
+        let conflicting = self.concurrency_mode != ConcurrencyMode::Mux
+            || self.port_range.is_some_and(|range| range != port_range)
+            || self.bind_address.is_some() && self.bind_address != bind_address;
+        if conflicting {
+            log::error!("mux socket {} conflicts with agent socket options", addr);
+            return Err(Error::InvalidArgument);
+        }
+        Ok((port_range, bind_address))
+    }
+
+    /// Build and start agent, same as [`Builder::prepare`] followed by [`PreparedAgent::start`].
     pub fn build(self) -> crate::Result<Agent> {
+        self.prepare()?.start()
+    }
+
+    /// Construct agent without starting it.
+    ///
+    /// The underlying libjuice agent and wrapper threads are created by [`PreparedAgent::start`],
+    /// no handler callback is invoked before. Meanwhile the agent address is fixed, so handlers
+    /// can be given [`PreparedAgent::downgrade`] handle to use the agent from the very first
+    /// callback.
+    pub fn prepare(self) -> crate::Result<PreparedAgent> {
         ensure_logging();
+        if !self.concurrency_mode.is_supported() {
+            log::error!(
+                "{:?} concurrency mode is not supported",
+                self.concurrency_mode
+            );
+            return Err(Error::UnsupportedMode(self.concurrency_mode));
+        }
+        let (port_range, bind_address) = self.socket_config()?;
 
-        let mut holder = Box::new(Holder {
-            agent: ptr::null_mut(),
-            handler: Mutex::new(self.handler),
-            _marker: PhantomData::default(),
-        });
+        let (supervisor, reconnect) = match self.reconnect {
+            Some((policy, on_restart)) => {
+                let (tx, rx) = channel();
+                (Some(Mutex::new(tx)), Some((policy, on_restart, rx)))
+            }
+            None => (None, None),
+        };
 
-        // [0..0] == no range
-        let port_range = self.port_range.unwrap_or((0, 0));
-        // default is google
-        let stun_server = self.stun_server.unwrap_or_default();
-        let bind_address = self
-            .bind_address
-            .as_ref()
-            .map(|v| v.as_ptr())
-            .unwrap_or(ptr::null());
+        let (batcher, batching) = match self.trickle_batching {
+            Some(window) => {
+                let (tx, rx) = channel();
+                (Some(Mutex::new(tx)), Some((window, rx)))
+            }
+            None => (None, None),
+        };
 
-        let servers = self
-            .turn_servers
-            .iter()
-            .map(|turn| sys::juice_turn_server {
-                host: turn.host.as_ptr(),
-                port: turn.port,
-                username: turn.username.as_ptr(),
-                password: turn.password.as_ptr(),
-            })
-            .collect::<Vec<_>>();
+        let (gathering, gathering_timer) = match self.gathering_timeout {
+            Some((timeout, on_timeout)) => {
+                let (tx, rx) = channel();
+                (Some(Gathering::new(tx, on_timeout)), Some((timeout, rx)))
+            }
+            None => (None, None),
+        };
 
-        let turn_servers = if servers.is_empty() {
-            (ptr::null(), 0)
-        } else {
-            (servers.as_ptr(), servers.len() as _)
+        let (watchdog, fallback) = match self.relay_fallback {
+            Some((silence, on_fallback)) => {
+                let (tx, rx) = channel();
+                (Some(tx), Some((silence, on_fallback, rx)))
+            }
+            None => (None, None),
         };
 
-        let config = &sys::juice_config {
-            stun_server_host: stun_server.0.as_ptr(),
-            stun_server_port: stun_server.1,
-            turn_servers: turn_servers.0 as _,
-            turn_servers_count: turn_servers.1,
-            bind_address,
-            local_port_range_begin: port_range.0,
-            local_port_range_end: port_range.1,
-            cb_state_changed: Some(on_state_changed),
-            cb_candidate: Some(on_candidate),
-            cb_gathering_done: Some(on_gathering_done),
-            cb_recv: Some(on_recv),
-            user_ptr: holder.as_mut() as *mut Holder as _,
+        #[cfg(feature = "metrics")]
+        let (exporter, metrics_interval) = match self.metrics_interval {
+            Some(interval) => {
+                let (tx, rx) = channel();
+                (Some(tx), Some((interval, rx)))
+            }
+            None => (None, None),
         };
 
-        let ptr = unsafe { sys::juice_create(config as _) };
-        if ptr.is_null() {
-            Err(Error::Failed)
-        } else {
-            holder.agent = ptr;
-            Ok(Agent { holder })
+        let holder = Arc::new(Holder {
+            agent: RwLock::new(ptr::null_mut()),
+            generation: AtomicU64::new(0),
+            slot: Mutex::new(None),
+            config: Config {
+                concurrency_mode: self.concurrency_mode,
+                // default is google
+                stun_server: (!self.no_stun).then(|| self.stun_server.unwrap_or_default()),
+                port_range,
+                bind_address,
+                turn_servers: self.turn_servers,
+            },
+            supervisor,
+            batcher,
+            gathering,
+            activity: watchdog::Activity::new(self.clock.clone()),
+            counters: Counters::default(),
+            estimator: (self.bandwidth_estimation || self.handler.has_bandwidth_handler())
+                .then(|| Mutex::new(Estimator::default())),
+            handler: HandlerCell::new(self.handler),
+            pacer: Mutex::new(None),
+            negotiation: Mutex::new(NegotiationState::default()),
+            remote_credentials: Mutex::new(None),
+            relays: Mutex::new(vec![]),
+            path: Mutex::new(None),
+            strict_signaling: self.strict_signaling,
+            address_policy: self.address_policy,
+            priority_rewriter: self
+                .priority_rewriter
+                .map(|f| (Mutex::new(f), self.rewrite_remote_priorities)),
+            default_candidate: self.default_candidate,
+            thread_options: (self.thread_options != ThreadOptions::default())
+                .then_some(self.thread_options),
+            clock: self.clock.clone(),
+            context: self.context,
+            surfaced: self
+                .normalize_candidates
+                .then(|| Mutex::new(HashSet::new())),
+            demux: OnceLock::new(),
+            negotiator: Mutex::new(None),
+            _watchdog: watchdog,
+            #[cfg(feature = "metrics")]
+            _exporter: exporter,
+            _marker: PhantomData::default(),
+        });
+
+        let mut threads: Vec<Spawner> = vec![];
+
+        if let Some((policy, on_restart, rx)) = reconnect {
+            let clock = self.clock.clone();
+            threads.push(Box::new(move |holder| {
+                reconnect::supervise(holder, clock, policy, on_restart, rx)
+            }));
+        }
+
+        if let Some((window, rx)) = batching {
+            let clock = self.clock.clone();
+            threads.push(Box::new(move |holder| {
+                trickle::batch(holder, clock, window, rx)
+            }));
+        }
+
+        if let Some((timeout, rx)) = gathering_timer {
+            let clock = self.clock.clone();
+            threads.push(Box::new(move |holder| {
+                gathering::watch(holder, clock, timeout, rx)
+            }));
         }
+
+        if let Some((silence, on_fallback, rx)) = fallback {
+            let clock = self.clock.clone();
+            threads.push(Box::new(move |holder| {
+                watchdog::watch(holder, clock, silence, on_fallback, rx)
+            }));
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some((interval, rx)) = metrics_interval {
+            let labels = match &holder.context {
+                Some((_, label)) => {
+                    let value = label.trim_end_matches(": ").to_string();
+                    vec![metrics::Label::new("agent", value)]
+                }
+                None => vec![],
+            };
+            let clock = self.clock.clone();
+            threads.push(Box::new(move |holder| {
+                exporter::export(holder, clock, interval, labels, rx)
+            }));
+        }
+
+        Ok(PreparedAgent { holder, threads })
+    }
+}
+
+/// Wrapper thread body, given the agent it serves
+type Spawner = Box<dyn FnOnce(Weak<Holder>) + Send + 'static>;
+
+/// Agent constructed by [`Builder::prepare`], not started yet.
+pub struct PreparedAgent {
+    holder: Arc<Holder>,
+    threads: Vec<Spawner>,
+}
+
+impl PreparedAgent {
+    /// Create non-owning handle, upgradable once the agent is started
+    pub fn downgrade(&self) -> WeakAgent {
+        WeakAgent {
+            holder: Arc::downgrade(&self.holder),
+        }
+    }
+
+    /// Create the underlying libjuice agent and wrapper threads, handler callbacks may be
+    /// invoked from now on. Events start with [`Agent::gather_candidates`].
+    pub fn start(self) -> crate::Result<Agent> {
+        let (raw, slot) = self.holder.create()?;
+        self.holder.publish(raw, slot);
+
+        for body in self.threads {
+            let holder = Arc::downgrade(&self.holder);
+            thread::spawn(move || body(holder));
+        }
+        Ok(Agent {
+            holder: self.holder,
+        })
     }
 }
 
 /// ICE agent.
+///
+/// Cheap to clone, clones share the same agent which is destroyed with the last one. Handlers
+/// should capture [`WeakAgent`] instead, a clone owned by the agent's own handler keeps it alive
+/// forever.
+#[derive(Clone)]
 pub struct Agent {
-    holder: Box<Holder>,
+    holder: Arc<Holder>,
+}
+
+/// Non-owning agent handle, see [`Agent::downgrade`].
+#[derive(Clone)]
+pub struct WeakAgent {
+    holder: Weak<Holder>,
+}
+
+impl WeakAgent {
+    /// Get the agent unless it was destroyed or is not started yet.
+    ///
+    /// An upgraded handle may be dropped in a handler even if it's the last one, the libjuice
+    /// agent is destroyed on a helper thread then.
+    pub fn upgrade(&self) -> Option<Agent> {
+        let holder = self.holder.upgrade()?;
+        // not started yet
+        if holder.generation.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        Some(Agent { holder })
+    }
 }
 
 impl Agent {
@@ -155,13 +638,56 @@ impl Agent {
         Builder::new(h)
     }
 
+    /// Create two agents connected to each other over 127.0.0.1, without STUN and TURN.
+    ///
+    /// Descriptions are exchanged internally, returns once both agents are connected.
+    /// Intended for local testing.
+    pub fn pair_loopback(first: Handler, second: Handler) -> Result<(Agent, Agent)> {
+        let localhost = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let (tx, rx) = channel();
+        let build = |handler: Handler| {
+            let tx = Mutex::new(tx.clone());
+            let handler = handler.chain_gathering_done(move || {
+                let _ = tx.lock().unwrap().send(());
+            });
+            Agent::builder(handler)
+                .without_stun()
+                .with_bind_address(&localhost)
+                .build()
+        };
+        let first = build(first)?;
+        let second = build(second)?;
+
+        first.gather_candidates()?;
+        second.gather_candidates()?;
+        let deadline = Instant::now() + LOOPBACK_TIMEOUT;
+        for _ in 0..2 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(timeout) {
+                return Err(Error::Failed);
+            }
+        }
+
+        second.set_remote_description(first.get_local_description()?)?;
+        first.set_remote_description(second.get_local_description()?)?;
+
+        let connected =
+            |agent: &Agent| matches!(agent.get_state(), State::Connected | State::Completed);
+        while !(connected(&first) && connected(&second)) {
+            let failed = [&first, &second]
+                .iter()
+                .any(|agent| agent.get_state() == State::Failed);
+            if failed || Instant::now() >= deadline {
+                return Err(Error::Failed);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok((first, second))
+    }
+
     /// Get ICE state
     pub fn get_state(&self) -> State {
-        unsafe {
-            sys::juice_get_state(self.holder.agent)
-                .try_into()
-                .expect("failed to convert state")
-        }
+        self.holder.state()
     }
 
     /// Get local sdp
@@ -169,7 +695,7 @@ impl Agent {
         let mut buf = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
         let res = unsafe {
             let res = sys::juice_get_local_description(
-                self.holder.agent,
+                *self.holder.agent.read().unwrap(),
                 buf.as_mut_ptr(),
                 buf.len() as _,
             );
@@ -177,49 +703,467 @@ impl Agent {
             let s = CStr::from_ptr(buf.as_mut_ptr());
             String::from_utf8_lossy(s.to_bytes())
         };
-        Ok(res.to_string())
+        Ok(self.holder.postprocess_description(&res))
     }
 
     /// Start ICE candidates gathering
     pub fn gather_candidates(&self) -> crate::Result<()> {
-        let ret = unsafe { sys::juice_gather_candidates(self.holder.agent) };
+        if let Some(gathering) = &self.holder.gathering {
+            gathering.start();
+        }
+        let ret = unsafe { sys::juice_gather_candidates(*self.holder.agent.read().unwrap()) };
         raw_retcode_to_result(ret)
     }
 
-    /// Set remote description
+    /// Set remote description, once per negotiation.
+    ///
+    /// A description with new ICE credentials is taken for a restart of the peer: the agent is
+    /// restarted first, as with [`Agent::restart`], and its new local description has to be
+    /// signaled back with [`Agent::get_local_description`].
     pub fn set_remote_description(&self, sdp: String) -> crate::Result<()> {
+        let mut negotiation = self.holder.negotiation.lock().unwrap();
+        if self.holder.strict_signaling {
+            sdp::validate(&sdp).map_err(Error::InvalidDescription)?;
+        }
+        let credentials = sdp::credentials(&sdp);
+        if *negotiation != NegotiationState::AwaitingDescription {
+            let known = self.holder.remote_credentials.lock().unwrap().clone();
+            if credentials.is_none() || credentials == known {
+                negotiation.expect(NegotiationState::AwaitingDescription)?;
+            }
+            log::info!(
+                "{}remote credentials changed, restarting",
+                self.holder.label()
+            );
+            self.holder.restart_with(Some(&mut negotiation))?;
+        }
+        let sdp = match &self.holder.address_policy {
+            Some(policy) => sdp::retain_candidates(&sdp, |c| {
+                let allowed = policy.is_candidate_allowed(c);
+                if !allowed {
+                    log::debug!("{}dropping remote candidate {}", self.holder.label(), c);
+                }
+                allowed
+            }),
+            None => sdp,
+        };
+        let sdp = self.holder.rewrite_priorities(sdp, true);
         let s = CString::new(sdp).map_err(|_| Error::InvalidArgument)?;
-        let ret = unsafe { sys::juice_set_remote_description(self.holder.agent, s.as_ptr()) };
-        raw_retcode_to_result(ret)
+        let ret = unsafe {
+            sys::juice_set_remote_description(*self.holder.agent.read().unwrap(), s.as_ptr())
+        };
+        raw_retcode_to_result(ret)?;
+        *negotiation = NegotiationState::DescriptionSet;
+        *self.holder.remote_credentials.lock().unwrap() = credentials;
+        Ok(())
+    }
+
+    /// Restart ICE with fresh credentials and start gathering, returns the new local
+    /// description to signal to the peer.
+    ///
+    /// The connection is dropped, the remote description of the peer has to be set again.
+    pub fn restart(&self) -> crate::Result<String> {
+        self.holder.restart()
     }
 
-    /// Add remote candidate
+    /// Add remote candidate, remote description must be set before
     pub fn add_remote_candidate(&self, sdp: String) -> crate::Result<()> {
+        let negotiation = self.holder.negotiation.lock().unwrap();
+        negotiation.expect(NegotiationState::DescriptionSet)?;
+        if let Some(policy) = &self.holder.address_policy {
+            // libjuice parses candidates the policy can't check, don't let them through
+            let allowed = match sdp.parse::<Candidate>() {
+                Ok(c) => policy.is_candidate_allowed(&c),
+                Err(_) => false,
+            };
+            if !allowed {
+                log::debug!("{}rejecting remote candidate {}", self.holder.label(), sdp);
+                return Err(Error::AddressRejected);
+            }
+        }
+        let sdp = self.holder.rewrite_priorities(sdp, true);
         let s = CString::new(sdp).map_err(|_| Error::InvalidArgument)?;
-        let ret = unsafe { sys::juice_add_remote_candidate(self.holder.agent, s.as_ptr()) };
+        let ret = unsafe {
+            sys::juice_add_remote_candidate(*self.holder.agent.read().unwrap(), s.as_ptr())
+        };
         raw_retcode_to_result(ret)
     }
 
-    /// Signal remote candidates exhausted
+    /// Signal remote candidates exhausted, remote description must be set before
     pub fn set_remote_gathering_done(&self) -> crate::Result<()> {
-        let ret = unsafe { sys::juice_set_remote_gathering_done(self.holder.agent) };
-        raw_retcode_to_result(ret)
+        let mut negotiation = self.holder.negotiation.lock().unwrap();
+        negotiation.expect(NegotiationState::DescriptionSet)?;
+        let ret =
+            unsafe { sys::juice_set_remote_gathering_done(*self.holder.agent.read().unwrap()) };
+        raw_retcode_to_result(ret)?;
+        *negotiation = NegotiationState::GatheringDone;
+        Ok(())
+    }
+
+    /// Replace event handler, e.g. to switch from a handshake phase handler to a data phase one.
+    ///
+    /// Can be called from the handler callbacks, the replacement takes effect from the next
+    /// event then. Bandwidth handler is invoked only if estimation was enabled at build.
+    pub fn set_handler(&self, handler: Handler) {
+        self.holder.handler.update(move |h| h.replace(handler))
     }
 
-    /// Send packet to remote endpoint
+    /// Drop incoming packet handlers, packets are discarded until [`Agent::set_handler`]
+    pub fn clear_recv_handler(&self) {
+        self.holder.handler.update(Handler::clear_recv)
+    }
+
+    /// Exchange descriptions and trickled candidates over `signaling` until connected.
+    ///
+    /// Starts gathering unless already started. Both agents are expected to negotiate
+    /// concurrently, fails with [`Error::Timeout`] if not connected within `timeout` and with
+    /// [`Error::Failed`] if connectivity checks fail.
+    pub fn negotiate<S>(&self, signaling: &mut S, timeout: Duration) -> crate::Result<()>
+    where
+        S: Signaling + ?Sized,
+    {
+        let (tx, rx) = channel();
+        *self.holder.negotiator.lock().unwrap() = Some(tx);
+        let res = self.run_negotiation(signaling, Instant::now() + timeout, rx);
+        *self.holder.negotiator.lock().unwrap() = None;
+        res
+    }
+
+    fn run_negotiation<S>(
+        &self,
+        signaling: &mut S,
+        deadline: Instant,
+        events: Receiver<signaling::Event>,
+    ) -> crate::Result<()>
+    where
+        S: Signaling + ?Sized,
+    {
+        if self.get_state() == State::Disconnected {
+            self.gather_candidates()?;
+        }
+        signaling.send_description(&self.get_local_description()?)?;
+
+        loop {
+            for event in events.try_iter() {
+                match event {
+                    signaling::Event::Candidate(sdp) => signaling.send_candidate(&sdp)?,
+                    signaling::Event::GatheringDone => {
+                        signaling.send_candidate(END_OF_CANDIDATES)?
+                    }
+                }
+            }
+            match self.get_state() {
+                State::Connected | State::Completed => return Ok(()),
+                State::Failed => return Err(Error::Failed),
+                _ => {}
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(Error::Timeout);
+            }
+            let slice = std::cmp::min(left, NEGOTIATION_POLL);
+            match self.negotiation_state() {
+                NegotiationState::AwaitingDescription => {
+                    if let Some(sdp) = signaling.recv_description(slice)? {
+                        self.set_remote_description(sdp)?;
+                    }
+                }
+                NegotiationState::DescriptionSet => match signaling.recv_candidate(slice)? {
+                    Some(sdp) if sdp.trim() == END_OF_CANDIDATES => {
+                        self.set_remote_gathering_done()?
+                    }
+                    Some(sdp) => {
+                        if let Err(e) = self.add_remote_candidate(sdp) {
+                            log::warn!("{}remote candidate skipped: {}", self.holder.label(), e);
+                        }
+                    }
+                    None => {}
+                },
+                NegotiationState::GatheringDone => thread::sleep(slice),
+            }
+        }
+    }
+
+    /// Get port of the socket shared in [`ConcurrencyMode::Mux`], known once gathering started
+    /// unless set explicitly
+    pub fn mux_port(&self) -> Option<u16> {
+        let config = &self.holder.config;
+        if config.concurrency_mode != ConcurrencyMode::Mux {
+            return None;
+        }
+        match config.port_range {
+            (begin, end) if begin != 0 && begin == end => Some(begin),
+            _ => self
+                .get_local_description()
+                .ok()?
+                .lines()
+                .filter_map(|line| line.parse::<Candidate>().ok())
+                .find(|c| c.kind() == CandidateType::Host)
+                .map(|c| c.port()),
+        }
+    }
+
+    /// Get number of [`Agent`] clones sharing the agent, for leak diagnostics
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.holder)
+    }
+
+    /// Create non-owning handle, e.g. for handlers of the agent itself
+    pub fn downgrade(&self) -> WeakAgent {
+        WeakAgent {
+            holder: Arc::downgrade(&self.holder),
+        }
+    }
+
+    /// Get concurrency mode
+    pub fn concurrency_mode(&self) -> ConcurrencyMode {
+        self.holder.config.concurrency_mode
+    }
+
+    /// Get remote signaling progress
+    pub fn negotiation_state(&self) -> NegotiationState {
+        *self.holder.negotiation.lock().unwrap()
+    }
+
+    /// Send packet to remote endpoint.
+    ///
+    /// Packets larger than 4096 bytes are rejected with [`Error::MessageTooLarge`], as the remote
+    /// agent would truncate them. Keep packets within [`Agent::mtu`] to avoid IP fragmentation.
+    ///
+    /// With pacing enabled the packet is queued, see [`Agent::set_pacing_rate`].
     pub fn send(&self, data: &[u8]) -> crate::Result<()> {
-        let ret =
-            unsafe { sys::juice_send(self.holder.agent, data.as_ptr() as _, data.len() as _) };
-        raw_retcode_to_result(ret)
+        self.holder.send(data)
+    }
+
+    /// Send several packets at once, returns number of packets sent.
+    ///
+    /// The agent is locked once for the whole batch, packets are still sent one by one with a
+    /// syscall each, libjuice has no batched send. Sending stops at the first failed packet,
+    /// error is returned only if nothing was sent.
+    pub fn send_batch(&self, packets: &[&[u8]]) -> crate::Result<usize> {
+        let pacer = self.holder.pacer.lock().unwrap();
+        let agent = self.holder.agent.read().unwrap();
+        for (sent, data) in packets.iter().enumerate() {
+            let ret = if data.len() > MAX_DATAGRAM_SIZE {
+                Err(Error::MessageTooLarge)
+            } else if let Some(pacer) = &*pacer {
+                pacer.enqueue(data)
+            } else {
+                self.holder.send_now(&agent, data)
+            };
+            match ret {
+                Ok(_) => {}
+                Err(e) if sent == 0 => return Err(e),
+                Err(_) => return Ok(sent),
+            }
+        }
+        Ok(packets.len())
+    }
+
+    /// Open logical stream multiplexed over the agent, see [`crate::subchannel`].
+    ///
+    /// Fails with [`Error::InvalidArgument`] if channel of given id is already open.
+    pub fn channel(&self, id: u16) -> crate::Result<SubChannel> {
+        SubChannel::open(&self.holder, id)
+    }
+
+    /// Pace outgoing packets at given rate in bits per second, 0 disables pacing.
+    ///
+    /// Paced packets are queued and sent evenly spaced by an internal thread, so bursts don't
+    /// trip the policer of a TURN relay. Sending fails with [`Error::NotAvailable`] while the
+    /// queue is full. Packets queued before pacing is disabled are still sent at the old rate.
+    pub fn set_pacing_rate(&self, bits_per_sec: u64) {
+        let mut pacer = self.holder.pacer.lock().unwrap();
+        match (&*pacer, bits_per_sec) {
+            (_, 0) => *pacer = None,
+            (Some(pacer), rate) => pacer.set_rate(rate),
+            (None, rate) => {
+                let holder = Arc::downgrade(&self.holder);
+                let clock = self.holder.clock.clone();
+                *pacer = Some(Pacer::spawn(rate, clock, move |packet| {
+                    match holder.upgrade() {
+                        Some(holder) => {
+                            let agent = holder.agent.read().unwrap();
+                            if let Err(e) = holder.send_now(&agent, packet) {
+                                log::debug!("{}paced packet dropped: {}", holder.label(), e);
+                            }
+                            true
+                        }
+                        None => false,
+                    }
+                }));
+            }
+        }
+    }
+
+    /// Get largest payload fitting into a single packet on the selected path.
+    ///
+    /// libjuice doesn't probe path MTU, so the value is derived from 1500 bytes link MTU minus IP,
+    /// UDP and TURN overhead if either side is relayed. Fails if no pair is selected yet.
+    pub fn mtu(&self) -> crate::Result<usize> {
+        let (_, remote, local_kind, remote_kind) = self.holder.selected_pair()?;
+        let ip_header = match remote {
+            SocketAddr::V4(_) => 20,
+            SocketAddr::V6(_) => 40,
+        };
+        let relayed = local_kind == CandidateType::Relayed || remote_kind == CandidateType::Relayed;
+        let relay = if relayed { TURN_OVERHEAD } else { 0 };
+        Ok(LINK_MTU - ip_header - UDP_HEADER_SIZE - relay)
+    }
+
+    /// Get data path statistics
+    pub fn stats(&self) -> Stats {
+        self.holder.counters.snapshot()
+    }
+
+    /// Get smoothed throughput and jitter estimates, `None` unless enabled with
+    /// [`Builder::with_bandwidth_estimation`]
+    pub fn bandwidth_estimate(&self) -> Option<BandwidthEstimate> {
+        let estimator = self.holder.estimator.as_ref()?;
+        Some(estimator.lock().unwrap().estimate(self.holder.clock.now()))
+    }
+
+    /// Get user data attached with [`Builder::with_context`], `None` if there is none or it is
+    /// not of type `T`
+    pub fn context<T: Any>(&self) -> Option<&T> {
+        let (context, _) = self.holder.context.as_ref()?;
+        context.downcast_ref()
+    }
+
+    /// Get relayed addresses allocated on TURN servers so far, one per successful allocation
+    pub fn relay_addresses(&self) -> Vec<SocketAddr> {
+        self.holder.relays.lock().unwrap().clone()
     }
 
     /// Get selected candidates pair (local,remote)
     pub fn get_selected_candidates(&self) -> crate::Result<(String, String)> {
+        self.holder.selected_candidates()
+    }
+
+    pub fn get_selected_addresses(&self) -> crate::Result<(String, String)> {
+        self.holder.selected_addresses()
+    }
+
+    /// Get selected pair as (local address, remote address, local type, remote type).
+    ///
+    /// Fails with [`Error::NotAvailable`] if no pair is selected yet.
+    pub fn selected_pair(
+        &self,
+    ) -> crate::Result<(SocketAddr, SocketAddr, CandidateType, CandidateType)> {
+        self.holder.selected_pair()
+    }
+}
+
+pub(crate) struct Holder {
+    agent: RwLock<*mut sys::juice_agent_t>,
+    /// Generation of the published agent, callbacks of other agents are ignored
+    generation: AtomicU64,
+    /// Callbacks context of the published agent
+    slot: Mutex<Option<Box<Slot>>>,
+    config: Config,
+    handler: HandlerCell,
+    supervisor: Option<Mutex<Sender<reconnect::Event>>>,
+    batcher: Option<Mutex<Sender<trickle::Event>>>,
+    /// Set if gathering timeout is enabled
+    gathering: Option<Gathering>,
+    activity: watchdog::Activity,
+    counters: Counters,
+    estimator: Option<Mutex<Estimator>>,
+    /// Paces outgoing packets when rate is set
+    pacer: Mutex<Option<Pacer>>,
+    /// Remote signaling progress, locked for the whole signaling call
+    negotiation: Mutex<NegotiationState>,
+    /// ICE credentials of the remote description set, taken with the negotiation lock held
+    remote_credentials: Mutex<Option<(String, String)>>,
+    /// Relayed addresses of local relayed candidates
+    relays: Mutex<Vec<SocketAddr>>,
+    /// Cached remote address of the selected pair and whether it is relayed
+    path: Mutex<Option<(SocketAddr, bool)>>,
+    strict_signaling: bool,
+    address_policy: Option<AddressPolicy>,
+    /// Candidate priorities override and whether it applies to remote candidates
+    priority_rewriter: Option<(Mutex<PriorityRewriter>, bool)>,
+    default_candidate: DefaultCandidate,
+    /// Applied to the libjuice thread, unset if default
+    thread_options: Option<ThreadOptions>,
+    /// Time source of wrapper-side timers
+    clock: Arc<dyn Clock>,
+    /// User data and log messages prefix
+    context: Option<(Arc<dyn Any + Send + Sync>, String)>,
+    /// Keys of local candidates passed to the handler, set if candidates are normalized
+    surfaced: Option<Mutex<HashSet<CandidateKey>>>,
+    /// Sub-channels recv handlers, installed on the first opened channel
+    demux: OnceLock<Arc<Demux>>,
+    /// Local events consumer of [`Agent::negotiate`] in progress
+    negotiator: Mutex<Option<Sender<signaling::Event>>>,
+    /// Keeps watchdog thread alive
+    _watchdog: Option<Sender<()>>,
+    /// Keeps metrics exporter thread alive
+    #[cfg(feature = "metrics")]
+    _exporter: Option<Sender<()>>,
+    _marker: PhantomData<(sys::juice_agent, std::marker::PhantomPinned)>,
+}
+
+impl Drop for Holder {
+    fn drop(&mut self) {
+        let agent = *self.agent.get_mut().unwrap();
+        if agent.is_null() {
+            return;
+        }
+        if !CallbackScope::is_active() {
+            unsafe { sys::juice_destroy(agent) };
+            return;
+        }
+        // libjuice waits for the thread running the callback in juice_destroy
+        log::debug!(
+            "{}agent dropped in a callback, destroying it on a helper thread",
+            self.label()
+        );
+        let agent = Stale(agent);
+        // callbacks until destroyed find the holder gone
+        let slot = self.slot.get_mut().unwrap().take();
+        thread::spawn(move || {
+            agent.destroy();
+            drop(slot);
+        });
+    }
+}
+
+/// libjuice agent left to destroy on a helper thread
+struct Stale(*mut sys::juice_agent_t);
+
+// SAFETY: the agent is not reachable from anywhere else
+unsafe impl Send for Stale {}
+
+impl Stale {
+    fn destroy(self) {
+        unsafe { sys::juice_destroy(self.0) }
+    }
+}
+
+// SAFETY: All juice calls protected by mutex internally and can be invoked from any thread
+unsafe impl Sync for Holder {}
+
+unsafe impl Send for Holder {}
+
+impl Holder {
+    /// Get ICE state
+    pub(crate) fn state(&self) -> State {
+        unsafe {
+            sys::juice_get_state(*self.agent.read().unwrap())
+                .try_into()
+                .expect("failed to convert state")
+        }
+    }
+
+    /// Get selected candidates pair (local,remote)
+    pub(crate) fn selected_candidates(&self) -> crate::Result<(String, String)> {
         let mut local = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
         let mut remote = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
         let ret = unsafe {
             let res = sys::juice_get_selected_candidates(
-                self.holder.agent,
+                *self.agent.read().unwrap(),
                 local.as_mut_ptr() as _,
                 local.len() as _,
                 remote.as_mut_ptr() as _,
@@ -236,12 +1180,13 @@ impl Agent {
         Ok(ret)
     }
 
-    pub fn get_selected_addresses(&self) -> crate::Result<(String, String)> {
+    /// Get selected addresses pair (local,remote)
+    pub(crate) fn selected_addresses(&self) -> crate::Result<(String, String)> {
         let mut local = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
         let mut remote = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
         let ret = unsafe {
             let res = sys::juice_get_selected_addresses(
-                self.holder.agent,
+                *self.agent.read().unwrap(),
                 local.as_mut_ptr() as _,
                 local.len() as _,
                 remote.as_mut_ptr() as _,
@@ -257,48 +1202,346 @@ impl Agent {
         };
         Ok(ret)
     }
-}
 
-pub(crate) struct Holder {
-    agent: *mut sys::juice_agent_t,
-    handler: Mutex<Handler>,
-    _marker: PhantomData<(sys::juice_agent, std::marker::PhantomPinned)>,
-}
+    /// Prefix of log messages identifying the agent, empty without context
+    pub(crate) fn label(&self) -> &str {
+        self.context.as_ref().map_or("", |(_, label)| label)
+    }
 
-impl Drop for Holder {
-    fn drop(&mut self) {
-        unsafe { sys::juice_destroy(self.agent) }
+    /// Create libjuice agent of the next generation, its callbacks are ignored until it is
+    /// published.
+    ///
+    /// Must not be called with the agent lock held: callbacks of the shared libjuice thread
+    /// take it while libjuice may wait for that thread in `juice_create`.
+    fn create(self: &Arc<Self>) -> Result<(*mut sys::juice_agent_t, Box<Slot>)> {
+        let slot = Box::new(Slot {
+            holder: Arc::downgrade(self),
+            generation: self.generation.load(Ordering::Acquire) + 1,
+        });
+        let raw = self.config.create(&*slot as *const Slot as _)?;
+        Ok((raw, slot))
     }
-}
 
-// SAFETY: All juice calls protected by mutex internally and can be invoked from any thread
-unsafe impl Sync for Holder {}
+    /// Make created agent the current one, returns the replaced agent and its context
+    fn publish(
+        &self,
+        raw: *mut sys::juice_agent_t,
+        slot: Box<Slot>,
+    ) -> (*mut sys::juice_agent_t, Option<Box<Slot>>) {
+        let mut agent = self.agent.write().unwrap();
+        self.generation.store(slot.generation, Ordering::Release);
+        let stale = self.slot.lock().unwrap().replace(slot);
+        (std::mem::replace(&mut *agent, raw), stale)
+    }
 
-unsafe impl Send for Holder {}
+    fn notify_negotiator(&self, event: signaling::Event) {
+        if let Some(tx) = &*self.negotiator.lock().unwrap() {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// Notify supervisor if any, returns false if event was not consumed
+    fn notify_supervisor(&self, event: reconnect::Event) -> bool {
+        match &self.supervisor {
+            Some(tx) => tx.lock().unwrap().send(event).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Replace underlying agent with a fresh one and start gathering, returns new local
+    /// description
+    pub(crate) fn restart(self: &Arc<Self>) -> Result<String> {
+        self.restart_with(None)
+    }
+
+    /// Restart, `negotiation` is the state locked by the caller if any
+    fn restart_with(
+        self: &Arc<Self>,
+        negotiation: Option<&mut NegotiationState>,
+    ) -> Result<String> {
+        let (fresh, slot) = self.create()?;
+        let (stale, stale_slot) = self.publish(fresh, slot);
+        // stale agent is not reachable anymore, its callbacks are ignored
+        unsafe { sys::juice_destroy(stale) };
+        drop(stale_slot);
+        self.activity.reset();
+        self.relays.lock().unwrap().clear();
+        {
+            let mut locked;
+            let negotiation = match negotiation {
+                Some(negotiation) => negotiation,
+                None => {
+                    locked = self.negotiation.lock().unwrap();
+                    &mut *locked
+                }
+            };
+            *negotiation = NegotiationState::default();
+            *self.remote_credentials.lock().unwrap() = None;
+        }
+        if let Some(surfaced) = &self.surfaced {
+            surfaced.lock().unwrap().clear();
+        }
+        *self.path.lock().unwrap() = None;
+
+        let mut buf = vec![0; sys::JUICE_MAX_SDP_STRING_LEN as _];
+        let description = unsafe {
+            let res = sys::juice_get_local_description(fresh, buf.as_mut_ptr(), buf.len() as _);
+            raw_retcode_to_result(res)?;
+            let s = CStr::from_ptr(buf.as_mut_ptr());
+            self.postprocess_description(&String::from_utf8_lossy(s.to_bytes()))
+        };
+        if let Some(gathering) = &self.gathering {
+            gathering.start();
+        }
+        raw_retcode_to_result(unsafe { sys::juice_gather_candidates(fresh) })?;
+
+        Ok(description)
+    }
+
+    /// Parse selected candidates and addresses, libjuice output is expected to be well-formed
+    fn selected_pair(&self) -> Result<(SocketAddr, SocketAddr, CandidateType, CandidateType)> {
+        let (local, remote) = self.selected_candidates()?;
+        let kind = |sdp: &str| {
+            sdp.parse::<Candidate>()
+                .map(|c| c.kind())
+                .map_err(|_| Error::Failed)
+        };
+        let (local_kind, remote_kind) = (kind(&local)?, kind(&remote)?);
+        let (local, remote) = self.selected_addresses()?;
+        let address = |s: &str| parse_address(s).ok_or(Error::Failed);
+        Ok((address(&local)?, address(&remote)?, local_kind, remote_kind))
+    }
+
+    /// Remote address of the selected pair and whether local candidate is relayed
+    fn selected_path(&self) -> Option<(SocketAddr, bool)> {
+        let (_, remote, local_kind, _) = self.selected_pair().ok()?;
+        Some((remote, local_kind == CandidateType::Relayed))
+    }
+
+    /// Build metadata of packet received at given time
+    fn recv_meta(&self, timestamp: Instant) -> RecvMeta {
+        let mut path = self.path.lock().unwrap();
+        if path.is_none() {
+            *path = self.selected_path();
+        }
+        let (remote, via_relay) =
+            path.unwrap_or_else(|| ((Ipv4Addr::UNSPECIFIED, 0).into(), false));
+        RecvMeta {
+            timestamp,
+            via_relay,
+            remote,
+        }
+    }
+
+    /// Apply thread options, called only on libjuice threads
+    fn configure_thread(&self) {
+        if let Some(options) = &self.thread_options {
+            options.apply()
+        }
+    }
 
-impl Holder {
     pub(crate) fn on_state_changed(&self, state: State) {
-        let mut h = self.handler.lock().unwrap();
-        h.on_state_changed(state)
+        // selected pair may change, resolved again on next packet
+        *self.path.lock().unwrap() = None;
+        let consumed = match state {
+            State::Failed => self.notify_supervisor(reconnect::Event::Failed),
+            State::Connected | State::Completed => {
+                if state == State::Connected {
+                    self.configure_thread();
+                }
+                self.activity.touch();
+                let _ = self.notify_supervisor(reconnect::Event::Connected);
+                false
+            }
+            _ => false,
+        };
+        if !consumed {
+            let mut h = self.handler.lock();
+            h.on_state_changed(state)
+        }
+    }
+
+    /// Apply priority rewriter to candidates of the description or a single candidate line
+    fn rewrite_priorities(&self, sdp: String, remote: bool) -> String {
+        match &self.priority_rewriter {
+            Some((f, all)) if !remote || *all => {
+                let f = f.lock().unwrap();
+                sdp::rewrite_priorities(&sdp, |c| f(c))
+            }
+            _ => sdp,
+        }
+    }
+
+    /// Apply normalization and connection line to local description
+    fn postprocess_description(&self, description: &str) -> String {
+        let description = &self.rewrite_priorities(description.to_string(), false);
+        match self.surfaced {
+            Some(_) => {
+                sdp::add_connection_line(&sdp::normalize(description), self.default_candidate)
+            }
+            None => sdp::add_connection_line(description, self.default_candidate),
+        }
     }
 
     pub(crate) fn on_candidate(&self, candidate: String) {
-        let mut h = self.handler.lock().unwrap();
-        h.on_candidate(candidate)
+        let candidate = self.rewrite_priorities(candidate, false);
+        let parsed = candidate.parse::<Candidate>();
+        if let (Some(gathering), Ok(c)) = (&self.gathering, &parsed) {
+            if !gathering.on_candidate(c) {
+                log::debug!(
+                    "{}candidate after gathering timeout skipped: {}",
+                    self.label(),
+                    c
+                );
+                return;
+            }
+        }
+        if let (Some(surfaced), Ok(c)) = (&self.surfaced, &parsed) {
+            if !surfaced.lock().unwrap().insert(sdp::candidate_key(c)) {
+                log::debug!("{}duplicate local candidate skipped: {}", self.label(), c);
+                return;
+            }
+        }
+        self.notify_negotiator(signaling::Event::Candidate(candidate.clone()));
+        let mut h = self.handler.lock();
+        h.on_candidate(candidate);
+
+        if let Ok(c) = &parsed {
+            self.activity.on_candidate(c);
+            if let (CandidateType::Relayed, Some(addr)) = (c.kind(), c.addr()) {
+                self.relays.lock().unwrap().push(addr);
+                h.on_relay_ready(addr);
+            }
+        }
+        match (parsed, &self.batcher) {
+            (Ok(c), Some(tx)) => {
+                let _ = tx.lock().unwrap().send(trickle::Event::Candidate(c));
+            }
+            (Ok(c), None) => h.on_candidates(vec![c]),
+            (Err(e), _) => log::warn!("{}failed to parse local candidate: {}", self.label(), e),
+        }
     }
 
     pub(crate) fn on_gathering_done(&self) {
-        let mut h = self.handler.lock().unwrap();
+        if let Some(gathering) = &self.gathering {
+            if !gathering.on_gathering_done() {
+                log::debug!("{}gathering done after timeout skipped", self.label());
+                return;
+            }
+        }
+        self.finish_gathering()
+    }
+
+    /// Report gathering done to the handler and the negotiation in progress
+    fn finish_gathering(&self) {
+        self.notify_negotiator(signaling::Event::GatheringDone);
+        if let Some(tx) = &self.batcher {
+            // delivered by batcher after pending candidates
+            if tx
+                .lock()
+                .unwrap()
+                .send(trickle::Event::GatheringDone)
+                .is_ok()
+            {
+                return;
+            }
+        }
+        let mut h = self.handler.lock();
         h.on_gathering_done()
     }
 
-    pub(crate) fn on_recv(&self, packet: &[u8]) {
-        let mut h = self.handler.lock().unwrap();
-        h.on_recv(packet)
+    /// Send packet, paced if pacing is enabled
+    pub(crate) fn send(&self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::MessageTooLarge);
+        }
+        match &*self.pacer.lock().unwrap() {
+            Some(pacer) => pacer.enqueue(data),
+            None => self.send_now(&self.agent.read().unwrap(), data),
+        }
+    }
+
+    /// Get sub-channels demultiplexer, routing channel packets to it from now on
+    pub(crate) fn demux(&self) -> &Arc<Demux> {
+        self.demux.get_or_init(|| {
+            let demux = Arc::new(Demux::default());
+            let filter = demux.clone();
+            self.handler.update(move |h| {
+                *h = std::mem::take(h).intercept_recv(move |packet| filter.dispatch(packet))
+            });
+            demux
+        })
+    }
+
+    /// Pass packet to libjuice right away
+    fn send_now(&self, agent: &*mut sys::juice_agent_t, data: &[u8]) -> Result<()> {
+        let ret = unsafe { sys::juice_send(*agent, data.as_ptr() as _, data.len() as _) };
+        raw_retcode_to_result(ret)?;
+        self.counters.on_send(data.len());
+        self.activity.on_send();
+        if let Some(estimator) = &self.estimator {
+            estimator
+                .lock()
+                .unwrap()
+                .on_send(data.len(), self.clock.now());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn on_recv(&self, packet: &[u8], timestamp: Instant) {
+        self.configure_thread();
+        self.activity.touch();
+        self.counters.on_recv(packet.len());
+        let estimate = self.estimator.as_ref().and_then(|estimator| {
+            let mut estimator = estimator.lock().unwrap();
+            estimator
+                .on_recv(packet.len(), timestamp)
+                .then(|| estimator.estimate(timestamp))
+        });
+        let mut h = self.handler.lock();
+        if let Some(estimate) = estimate {
+            h.on_bandwidth(estimate);
+        }
+        h.on_recv(packet, || self.recv_meta(timestamp))
+    }
+}
+
+/// Agent concurrency mode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConcurrencyMode {
+    /// Connections share a single thread
+    #[default]
+    Poll,
+    /// Connections are multiplexed on a single UDP socket, see [`Builder::with_mux_socket`]
+    Mux,
+    /// Each connection runs in its own thread
+    Thread,
+}
+
+impl ConcurrencyMode {
+    /// Check whether the linked libjuice supports the mode, [`ConcurrencyMode::Mux`] appeared in
+    /// 1.1.0. Assumed supported if libjuice version is unknown.
+    pub fn is_supported(self) -> bool {
+        match self {
+            ConcurrencyMode::Mux => version().is_none_or(|v| (v.major, v.minor) >= (1, 1)),
+            ConcurrencyMode::Poll | ConcurrencyMode::Thread => true,
+        }
+    }
+}
+
+impl From<ConcurrencyMode> for sys::juice_concurrency_mode {
+    fn from(mode: ConcurrencyMode) -> Self {
+        match mode {
+            ConcurrencyMode::Poll => sys::juice_concurrency_mode_JUICE_CONCURRENCY_MODE_POLL,
+            ConcurrencyMode::Mux => sys::juice_concurrency_mode_JUICE_CONCURRENCY_MODE_MUX,
+            ConcurrencyMode::Thread => sys::juice_concurrency_mode_JUICE_CONCURRENCY_MODE_THREAD,
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum State {
     Disconnected,
     Gathering,
@@ -351,15 +1594,145 @@ struct TurnServer {
     pub port: u16,
 }
 
+/// Callbacks context of a libjuice agent, outlives the agent.
+struct Slot {
+    holder: Weak<Holder>,
+    /// Agents of a holder are numbered from 1
+    generation: u64,
+}
+
+thread_local! {
+    /// Number of agent callbacks running on this thread
+    static CALLBACK_DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Marks the thread as running an agent callback while alive
+struct CallbackScope;
+
+impl CallbackScope {
+    fn enter() -> Self {
+        CALLBACK_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Self
+    }
+
+    fn is_active() -> bool {
+        CALLBACK_DEPTH.with(|depth| depth.get() > 0)
+    }
+}
+
+impl Drop for CallbackScope {
+    fn drop(&mut self) {
+        CALLBACK_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Holder of the agent invoking a callback, kept alive until the callback returns
+struct Current {
+    // dropped before the scope, destruction in the callback is detected
+    holder: Arc<Holder>,
+    _scope: CallbackScope,
+}
+
+impl Deref for Current {
+    type Target = Holder;
+
+    fn deref(&self) -> &Holder {
+        &self.holder
+    }
+}
+
+/// Get holder of the agent invoking callback, `None` unless the agent is the published one
+unsafe fn current_holder(user_ptr: *mut c_void) -> Option<Current> {
+    let slot = &*(user_ptr as *const Slot);
+    let scope = CallbackScope::enter();
+    let holder = slot.holder.upgrade()?;
+    if slot.generation != holder.generation.load(Ordering::Acquire) {
+        log::debug!("{}ignoring event of replaced agent", holder.label());
+        return None;
+    }
+    Some(Current {
+        holder,
+        _scope: scope,
+    })
+}
+
+/// Agent configuration, kept alive to be able to recreate the agent.
+struct Config {
+    concurrency_mode: ConcurrencyMode,
+    stun_server: Option<StunServer>,
+    port_range: (u16, u16),
+    bind_address: Option<CString>,
+    turn_servers: Vec<TurnServer>,
+}
+
+impl Config {
+    /// Create raw agent delivering events to given context
+    fn create(&self, slot: *const Slot) -> Result<*mut sys::juice_agent_t> {
+        let bind_address = self
+            .bind_address
+            .as_ref()
+            .map(|v| v.as_ptr())
+            .unwrap_or(ptr::null());
+
+        let servers = self
+            .turn_servers
+            .iter()
+            .map(|turn| sys::juice_turn_server {
+                host: turn.host.as_ptr(),
+                port: turn.port,
+                username: turn.username.as_ptr(),
+                password: turn.password.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let turn_servers = if servers.is_empty() {
+            (ptr::null(), 0)
+        } else {
+            (servers.as_ptr(), servers.len() as _)
+        };
+
+        let (stun_host, stun_port) = match &self.stun_server {
+            Some(server) => (server.0.as_ptr(), server.1),
+            None => (ptr::null(), 0),
+        };
+
+        let config = &sys::juice_config {
+            concurrency_mode: self.concurrency_mode.into(),
+            stun_server_host: stun_host,
+            stun_server_port: stun_port,
+            turn_servers: turn_servers.0 as _,
+            turn_servers_count: turn_servers.1,
+            bind_address,
+            local_port_range_begin: self.port_range.0,
+            local_port_range_end: self.port_range.1,
+            cb_state_changed: Some(on_state_changed),
+            cb_candidate: Some(on_candidate),
+            cb_gathering_done: Some(on_gathering_done),
+            cb_recv: Some(on_recv),
+            user_ptr: slot as _,
+        };
+
+        let ptr = unsafe { sys::juice_create(config as _) };
+        if ptr.is_null() {
+            Err(Error::Failed)
+        } else {
+            Ok(ptr)
+        }
+    }
+}
+
 unsafe extern "C" fn on_state_changed(
     _: *mut sys::juice_agent_t,
     state: sys::juice_state_t,
     user_ptr: *mut c_void,
 ) {
-    let agent: &Holder = &*(user_ptr as *const _);
+    let agent = match current_holder(user_ptr) {
+        Some(agent) => agent,
+        None => return,
+    };
 
     if let Err(e) = state.try_into().map(|s| agent.on_state_changed(s)) {
-        log::error!("failed to map state {:?}", e)
+        log::error!("{}failed to map state {:?}", agent.label(), e)
     }
 }
 
@@ -368,7 +1741,10 @@ unsafe extern "C" fn on_candidate(
     sdp: *const c_char,
     user_ptr: *mut c_void,
 ) {
-    let agent: &Holder = &*(user_ptr as *const _);
+    let agent = match current_holder(user_ptr) {
+        Some(agent) => agent,
+        None => return,
+    };
     let candidate = {
         let s = CStr::from_ptr(sdp);
         String::from_utf8_lossy(s.to_bytes())
@@ -377,7 +1753,10 @@ unsafe extern "C" fn on_candidate(
 }
 
 unsafe extern "C" fn on_gathering_done(_: *mut sys::juice_agent_t, user_ptr: *mut c_void) {
-    let agent: &Holder = &*(user_ptr as *const _);
+    let agent = match current_holder(user_ptr) {
+        Some(agent) => agent,
+        None => return,
+    };
     agent.on_gathering_done()
 }
 
@@ -387,14 +1766,19 @@ unsafe extern "C" fn on_recv(
     len: sys::size_t,
     user_ptr: *mut c_void,
 ) {
-    let agent: &Holder = &*(user_ptr as *const _);
+    let agent = match current_holder(user_ptr) {
+        Some(agent) => agent,
+        None => return,
+    };
+    let timestamp = agent.clock.now();
     let packet = core::slice::from_raw_parts(data as _, len as _);
-    agent.on_recv(packet)
+    agent.on_recv(packet, timestamp)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
     use crate::Handler;
     use std::sync::{Arc, Barrier};
 
@@ -412,6 +1796,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn prepare() {
+        let prepared = Agent::builder(Handler::default())
+            .with_reconnect_policy(ReconnectPolicy::default(), |_| {})
+            .prepare()
+            .unwrap();
+        let weak = prepared.downgrade();
+        // no libjuice agent nor thread until started
+        assert!(weak.upgrade().is_none());
+        assert_eq!(prepared.holder.generation.load(Ordering::Acquire), 0);
+        drop(prepared);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn context() {
+        crate::test_util::logger_init();
+
+        let tagged = crate::TaggedHandler::default();
+        let agent = tagged.builder("session-1").build().unwrap();
+        assert_eq!(agent.context::<&str>(), Some(&"session-1"));
+        assert_eq!(agent.context::<String>(), None);
+        assert_eq!(agent.holder.label(), "\"session-1\": ");
+    }
+
+    #[test]
+    fn simulated_clock() {
+        crate::test_util::logger_init();
+
+        let clock = Arc::new(MockClock::new());
+        let agent = Agent::builder(Handler::default())
+            .with_clock(clock.clone())
+            .build()
+            .unwrap();
+
+        clock.sleep(Duration::from_secs(30));
+        assert_eq!(agent.holder.activity.silence(), Duration::from_secs(30));
+    }
+
     #[test]
     fn gather() {
         crate::test_util::logger_init();
@@ -447,4 +1870,144 @@ mod tests {
             agent.get_local_description().unwrap()
         );
     }
+
+    #[test]
+    fn peer_restart() {
+        crate::test_util::logger_init();
+
+        let remote = Agent::builder(Handler::default()).build().unwrap();
+        let agent = Agent::builder(Handler::default()).build().unwrap();
+        let description = remote.get_local_description().unwrap();
+        agent.set_remote_description(description.clone()).unwrap();
+        agent.set_remote_gathering_done().unwrap();
+        assert_eq!(
+            agent.set_remote_description(description),
+            Err(Error::InvalidState(
+                NegotiationState::AwaitingDescription,
+                NegotiationState::GatheringDone
+            ))
+        );
+
+        let local = agent.get_local_description().unwrap();
+        let restarted = remote.restart().unwrap();
+        assert_eq!(agent.set_remote_description(restarted), Ok(()));
+        assert_eq!(agent.negotiation_state(), NegotiationState::DescriptionSet);
+        assert_ne!(agent.get_local_description().unwrap(), local);
+        remote
+            .set_remote_description(agent.get_local_description().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn drop_in_callback() {
+        crate::test_util::logger_init();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let owner: Arc<Mutex<Option<Agent>>> = Default::default();
+        let handler = Handler::default().gathering_done_handler({
+            let owner = owner.clone();
+            let tx = Mutex::new(tx);
+            move || {
+                // the last handle, libjuice agent is destroyed on another thread
+                drop(owner.lock().unwrap().take());
+                let _ = tx.lock().unwrap().send(());
+            }
+        });
+        let agent = Agent::builder(handler).build().unwrap();
+        let weak = agent.downgrade();
+        *owner.lock().unwrap() = Some(agent);
+        weak.upgrade().unwrap().gather_candidates().unwrap();
+
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn address_policy() {
+        crate::test_util::logger_init();
+
+        let remote = Agent::builder(Handler::default()).build().unwrap();
+        let agent = Agent::builder(Handler::default())
+            .with_remote_address_policy(AddressPolicy::deny_internal())
+            .build()
+            .unwrap();
+        agent
+            .set_remote_description(remote.get_local_description().unwrap())
+            .unwrap();
+
+        let public = "a=candidate:1 1 UDP 2122317823 203.0.113.7 5000 typ host";
+        assert_eq!(agent.add_remote_candidate(public.into()), Ok(()));
+        let internal = "a=candidate:2 1 UDP 2122317823 10.0.0.1 5000 typ host";
+        assert_eq!(
+            agent.add_remote_candidate(internal.into()),
+            Err(Error::AddressRejected)
+        );
+        // out of range priority, parsed by libjuice but not checkable by the policy
+        let malformed = "a=candidate:3 1 UDP -1 10.0.0.1 5000 typ host";
+        assert_eq!(
+            agent.add_remote_candidate(malformed.into()),
+            Err(Error::AddressRejected)
+        );
+    }
+
+    #[test]
+    fn mux_socket_conflicts() {
+        let addr = "127.0.0.1:6000".parse().unwrap();
+        let builder = || Agent::builder(Handler::default()).with_mux_socket(addr);
+        let conflicting = [
+            builder().with_concurrency_mode(ConcurrencyMode::Poll),
+            builder().with_port_range(6000, 6010),
+            builder().with_bind_address(&"127.0.0.2".parse().unwrap()),
+        ];
+        for builder in conflicting {
+            assert_eq!(builder.build().err(), Some(Error::InvalidArgument));
+        }
+
+        let (range, bind) = builder()
+            .with_port_range(6000, 6000)
+            .with_bind_address(&"127.0.0.1".parse().unwrap())
+            .socket_config()
+            .unwrap();
+        assert_eq!(range, (6000, 6000));
+        assert_eq!(bind.unwrap().to_str(), Ok("127.0.0.1"));
+    }
+
+    #[test]
+    fn supported_modes() {
+        assert!(ConcurrencyMode::Poll.is_supported());
+        assert!(ConcurrencyMode::Thread.is_supported());
+        assert_eq!(
+            Error::UnsupportedMode(ConcurrencyMode::Mux).to_string(),
+            "unsupported concurrency mode Mux"
+        );
+    }
+
+    #[test]
+    fn address() {
+        assert_eq!(
+            parse_address("192.168.1.1:5000"),
+            Some("192.168.1.1:5000".parse().unwrap())
+        );
+        assert_eq!(
+            parse_address("[fe80::1]:5000"),
+            Some("[fe80::1]:5000".parse().unwrap())
+        );
+        assert_eq!(
+            parse_address("fe80::1:5000"),
+            Some("[fe80::1]:5000".parse().unwrap())
+        );
+        assert_eq!(parse_address("192.168.1.1"), None);
+        assert_eq!(parse_address("host:5000"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn state_serde() {
+        let json = serde_json::to_string(&State::Connected).unwrap();
+        assert_eq!(json, "\"Connected\"");
+        assert_eq!(
+            serde_json::from_str::<State>(&json).unwrap(),
+            State::Connected
+        );
+    }
 }
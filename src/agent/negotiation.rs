@@ -0,0 +1,49 @@
+//! Remote signaling order tracking.
+use crate::error::Error;
+use crate::Result;
+
+/// Progress of remote side signaling.
+///
+/// Remote description must be set once, followed by any number of remote candidates and an
+/// optional end of remote candidates. Out of order calls fail with [`Error::InvalidState`].
+/// A restart of either side, or a remote description with new ICE credentials, starts over.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NegotiationState {
+    /// Waiting for remote description
+    #[default]
+    AwaitingDescription,
+    /// Remote description set, remote candidates may be added
+    DescriptionSet,
+    /// Remote gathering signaled done, no more candidates expected
+    GatheringDone,
+}
+
+impl NegotiationState {
+    /// Check that the state is `expected`
+    pub(crate) fn expect(self, expected: NegotiationState) -> Result<()> {
+        if self == expected {
+            Ok(())
+        } else {
+            Err(Error::InvalidState(expected, self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expect() {
+        let state = NegotiationState::default();
+        assert_eq!(state.expect(NegotiationState::AwaitingDescription), Ok(()));
+        assert_eq!(
+            state.expect(NegotiationState::DescriptionSet),
+            Err(Error::InvalidState(
+                NegotiationState::DescriptionSet,
+                NegotiationState::AwaitingDescription
+            ))
+        );
+    }
+}
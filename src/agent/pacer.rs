@@ -0,0 +1,111 @@
+//! Outgoing packets pacing.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::Result;
+
+/// Packets waiting for their send time, further packets are rejected
+const QUEUE_LIMIT: usize = 1024;
+/// Sending credit accumulated while idle
+const MAX_BURST: Duration = Duration::from_millis(5);
+
+/// Handle of the pacer thread, the thread sends queued packets and exits once dropped.
+pub(crate) struct Pacer {
+    tx: SyncSender<Vec<u8>>,
+    /// Bits per second
+    rate: Arc<AtomicU64>,
+}
+
+impl Pacer {
+    /// Start pacing at `rate` bits per second, `send` returns false if packets can't be sent
+    /// anymore
+    pub(crate) fn spawn<F>(rate: u64, clock: Arc<dyn Clock>, send: F) -> Self
+    where
+        F: FnMut(&[u8]) -> bool,
+        F: Send + 'static,
+    {
+        let (tx, rx) = sync_channel(QUEUE_LIMIT);
+        let rate = Arc::new(AtomicU64::new(rate));
+        let shared = rate.clone();
+        thread::spawn(move || pace(shared, clock, send, rx));
+        Self { tx, rate }
+    }
+
+    pub(crate) fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Queue packet, fails with [`Error::NotAvailable`] if the queue is full
+    pub(crate) fn enqueue(&self, packet: &[u8]) -> Result<()> {
+        match self.tx.try_send(packet.to_vec()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Error::NotAvailable),
+            Err(TrySendError::Disconnected(_)) => Err(Error::Failed),
+        }
+    }
+}
+
+/// Time to transmit `len` bytes at `rate` bits per second
+fn transmit_time(len: usize, rate: u64) -> Duration {
+    Duration::from_secs_f64(len as f64 * 8.0 / rate as f64)
+}
+
+/// Pacer loop, lives until the handle is dropped and queued packets are sent.
+fn pace<F>(rate: Arc<AtomicU64>, clock: Arc<dyn Clock>, mut send: F, packets: Receiver<Vec<u8>>)
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    let mut next = clock.now();
+    while let Ok(packet) = packets.recv() {
+        let now = clock.now();
+        if next > now {
+            clock.sleep(next - now);
+        } else if let Some(earliest) = now.checked_sub(MAX_BURST) {
+            next = next.max(earliest);
+        }
+        if !send(&packet) {
+            break;
+        }
+        next += transmit_time(packet.len(), rate.load(Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{MockClock, SystemClock};
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn paced() {
+        let clock = Arc::new(MockClock::new());
+        let (tx, rx) = channel();
+        // 1000 bytes every 100ms
+        let pacer = Pacer::spawn(80_000, clock.clone(), move |packet| {
+            tx.send(packet.len()).is_ok()
+        });
+
+        for _ in 0..5 {
+            pacer.enqueue(&[0; 1000]).unwrap();
+        }
+        drop(pacer);
+
+        assert_eq!(rx.iter().sum::<usize>(), 5000);
+        assert_eq!(clock.elapsed(), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn queue_limit() {
+        let pacer = Pacer::spawn(8, Arc::new(SystemClock), |_| true);
+        let rejected = (0..QUEUE_LIMIT + 2)
+            .map(|_| pacer.enqueue(&[0; 100]))
+            .filter(|r| *r == Err(Error::NotAvailable))
+            .count();
+        assert!(rejected > 0);
+    }
+}
@@ -0,0 +1,248 @@
+//! Remote candidate address filtering.
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::agent::candidate::Candidate;
+use crate::{Error, Result};
+
+/// IP network in CIDR notation, e.g. "10.0.0.0/8" or "fe80::/10".
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Create network, fails if prefix is longer than the address
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    /// Get prefix length
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Check whether address belongs to the network, IPv4-mapped IPv6 addresses match IPv4
+    /// networks
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(*addr, IpAddr::V4),
+            v4 => *v4,
+        };
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| Error::InvalidArgument)?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| Error::InvalidArgument)?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix)
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Ranges not reachable from the public internet
+const INTERNAL: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    // NAT64 and 6to4 embed any IPv4 address, including internal ones
+    "64:ff9b::/96",
+    "2002::/16",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// Allow and deny lists for remote candidate addresses.
+///
+/// The most specific (longest prefix) matching rule decides, deny wins between rules of equal
+/// prefix, the default applies if nothing matches. Hostname candidates are rejected unless
+/// allowed explicitly, as resolving them is beyond the policy control.
+///
+/// # Example
+/// ```
+/// # use libjuice_rs::AddressPolicy;
+/// // public addresses and the internal TURN relay network only
+/// let policy = AddressPolicy::deny_internal()
+///     .allow("10.1.0.0/16".parse().unwrap());
+/// assert!(policy.is_allowed(&"203.0.113.7".parse().unwrap()));
+/// assert!(policy.is_allowed(&"10.1.2.3".parse().unwrap()));
+/// assert!(!policy.is_allowed(&"10.2.0.1".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressPolicy {
+    default_allow: bool,
+    allow_hostnames: bool,
+    rules: Vec<(Cidr, bool)>,
+}
+
+impl AddressPolicy {
+    /// Allow addresses not matching any rule
+    pub fn allow_all() -> Self {
+        Self {
+            default_allow: true,
+            allow_hostnames: false,
+            rules: vec![],
+        }
+    }
+
+    /// Deny addresses not matching any rule
+    pub fn deny_all() -> Self {
+        Self {
+            default_allow: false,
+            ..Self::allow_all()
+        }
+    }
+
+    /// Allow all but private, loopback, link-local, multicast and other internal ranges.
+    ///
+    /// NAT64 (`64:ff9b::/96`) and 6to4 (`2002::/16`) addresses are denied as a whole, as they
+    /// can carry an internal IPv4 address. Allow them explicitly to accept such peers.
+    pub fn deny_internal() -> Self {
+        INTERNAL
+            .iter()
+            .map(|net| net.parse().unwrap())
+            .fold(Self::allow_all(), Self::deny)
+    }
+
+    /// Add allowed network
+    pub fn allow(mut self, net: Cidr) -> Self {
+        self.rules.push((net, true));
+        self
+    }
+
+    /// Add denied network
+    pub fn deny(mut self, net: Cidr) -> Self {
+        self.rules.push((net, false));
+        self
+    }
+
+    /// Accept candidates with hostname (e.g. mDNS) instead of IP address (default is false)
+    pub fn allow_hostnames(mut self, allow: bool) -> Self {
+        self.allow_hostnames = allow;
+        self
+    }
+
+    /// Check address against the rules
+    pub fn is_allowed(&self, addr: &IpAddr) -> bool {
+        self.rules
+            .iter()
+            .filter(|(net, _)| net.contains(addr))
+            .max_by_key(|(net, allow)| (net.prefix, !allow))
+            .map_or(self.default_allow, |(_, allow)| *allow)
+    }
+
+    /// Check candidate connection address
+    pub(crate) fn is_candidate_allowed(&self, candidate: &Candidate) -> bool {
+        match candidate.address().parse::<IpAddr>() {
+            Ok(addr) => self.is_allowed(&addr),
+            Err(_) => self.allow_hostnames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr() {
+        let net: Cidr = "172.16.0.0/12".parse().unwrap();
+        assert!(net.contains(&ip("172.31.255.1")));
+        assert!(!net.contains(&ip("172.32.0.1")));
+        assert!(net.contains(&ip("::ffff:172.16.0.1")));
+        assert!(!net.contains(&ip("fe80::1")));
+        assert_eq!(net.to_string(), "172.16.0.0/12");
+
+        let all: Cidr = "::/0".parse().unwrap();
+        assert!(all.contains(&ip("2001:db8::1")));
+        let host: Cidr = "192.0.2.1".parse().unwrap();
+        assert_eq!(host.prefix(), 32);
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("host/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn policy() {
+        let policy = AddressPolicy::deny_internal();
+        assert!(policy.is_allowed(&ip("8.8.8.8")));
+        assert!(policy.is_allowed(&ip("2001:db8::1")));
+        for denied in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "198.18.0.1",
+            "192.0.0.8",
+            "fd00::1",
+            "64:ff9b::a00:1",
+            "2002:c0a8:1::1",
+        ] {
+            assert!(!policy.is_allowed(&ip(denied)), "{}", denied);
+        }
+
+        let policy = AddressPolicy::deny_all()
+            .allow("198.51.100.0/24".parse().unwrap())
+            .deny("198.51.100.128/25".parse().unwrap());
+        assert!(policy.is_allowed(&ip("198.51.100.1")));
+        assert!(!policy.is_allowed(&ip("198.51.100.200")));
+        assert!(!policy.is_allowed(&ip("203.0.113.1")));
+
+        let mdns: Candidate = "a=candidate:1 1 UDP 2122317823 abcd.local 54321 typ host"
+            .parse()
+            .unwrap();
+        assert!(!policy.is_candidate_allowed(&mdns));
+        assert!(policy.allow_hostnames(true).is_candidate_allowed(&mdns));
+    }
+}
@@ -0,0 +1,264 @@
+//! Automatic ICE restart.
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::agent::{Holder, State};
+use crate::clock::{self, Clock};
+use crate::Error;
+
+/// Reconnect policy with exponential backoff.
+///
+/// # Example
+/// ```
+/// # use std::time::Duration;
+/// # use libjuice_rs::ReconnectPolicy;
+/// let policy = ReconnectPolicy::new(3).with_backoff(Duration::from_millis(500), Duration::from_secs(5));
+/// assert_eq!(policy.backoff(1), Some(Duration::from_millis(500)));
+/// assert_eq!(policy.backoff(2), Some(Duration::from_secs(1)));
+/// assert_eq!(policy.backoff(4), None);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ReconnectPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Create policy with given attempts limit and default backoff (1s doubling up to 30s)
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    /// Set initial delay before the first attempt and maximal delay between attempts
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Get delay before given attempt (starting from 1), `None` if policy is exhausted
+    pub fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt == 0 || attempt > self.max_attempts {
+            return None;
+        }
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        let delay = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff);
+        Some(std::cmp::min(delay, self.max_backoff))
+    }
+}
+
+/// Invoked with the new local description after restart
+pub(crate) type RestartHandler = Box<dyn FnMut(String) + Send + 'static>;
+
+/// Agent events the supervisor is interested in
+pub(crate) enum Event {
+    Failed,
+    Connected,
+    /// Connected path went silent, restart without spending an attempt
+    Stalled,
+}
+
+/// Restart decided by the supervisor
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Restart after failure, with the attempt number
+    Restart(u32),
+    /// Restart of stalled agent, not counted as attempt
+    RestartStalled,
+    /// Policy exhausted, agent failed
+    Exhausted,
+}
+
+/// Supervisor loop, lives until the agent is dropped
+pub(crate) fn supervise(
+    holder: Weak<Holder>,
+    clock: Arc<dyn Clock>,
+    policy: ReconnectPolicy,
+    mut on_restart: RestartHandler,
+    events: Receiver<Event>,
+) {
+    run(&*clock, policy, &events, |action| {
+        let holder = match holder.upgrade() {
+            Some(holder) => holder,
+            None => return false,
+        };
+        let restarted = match action {
+            Action::RestartStalled => {
+                log::info!("{}restarting stalled agent", holder.label());
+                match holder.restart() {
+                    Ok(description) => on_restart(description),
+                    Err(e) => {
+                        log::warn!("{}failed to restart stalled agent: {}", holder.label(), e)
+                    }
+                }
+                return true;
+            }
+            Action::Restart(attempt) => {
+                log::info!("{}restarting agent, attempt {}", holder.label(), attempt);
+                holder.restart()
+            }
+            Action::Exhausted => {
+                log::warn!("{}reconnect attempts exhausted", holder.label());
+                Err(Error::Failed)
+            }
+        };
+
+        match restarted {
+            Ok(description) => on_restart(description),
+            Err(e) => {
+                log::error!("{}agent failed: {}", holder.label(), e);
+                let mut h = holder.handler.lock();
+                h.on_state_changed(State::Failed)
+            }
+        }
+        true
+    })
+}
+
+/// Turn agent events into restarts until `act` returns false or events are disconnected.
+///
+/// Failure restarts are delayed by the policy backoff. Recovery during the backoff cancels the
+/// pending restart, a stall restarts at once and supersedes it.
+fn run<F>(clock: &dyn Clock, policy: ReconnectPolicy, events: &Receiver<Event>, mut act: F)
+where
+    F: FnMut(Action) -> bool,
+{
+    let mut attempt = 0;
+    // deadline of the restart after failure
+    let mut pending: Option<Instant> = None;
+
+    loop {
+        let event = match pending {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(clock.now());
+                clock::recv_timeout(clock, events, left)
+            }
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let action = match event {
+            Ok(Event::Connected) => {
+                if pending.take().is_some() {
+                    log::info!("agent recovered during backoff, restart cancelled");
+                }
+                attempt = 0;
+                continue;
+            }
+            Ok(Event::Stalled) => {
+                pending = None;
+                Action::RestartStalled
+            }
+            Ok(Event::Failed) if pending.is_some() => {
+                log::debug!("agent failure during backoff, restart is pending already");
+                continue;
+            }
+            Ok(Event::Failed) => {
+                attempt += 1;
+                match policy.backoff(attempt) {
+                    Some(delay) => {
+                        pending = Some(clock.now() + delay);
+                        continue;
+                    }
+                    None => Action::Exhausted,
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                pending = None;
+                Action::Restart(attempt)
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if !act(action) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn backoff() {
+        let policy =
+            ReconnectPolicy::new(6).with_backoff(Duration::from_secs(1), Duration::from_secs(10));
+
+        let delays = (0..=7).map(|i| policy.backoff(i)).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                None,
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(4)),
+                Some(Duration::from_secs(8)),
+                Some(Duration::from_secs(10)),
+                Some(Duration::from_secs(10)),
+                None,
+            ]
+        );
+    }
+
+    /// Run supervisor over queued events until `count` actions, returns them with clock time
+    fn actions(events: Vec<Event>, count: usize) -> Vec<(Action, Duration)> {
+        let clock = MockClock::new();
+        let (tx, rx) = channel();
+        for event in events {
+            tx.send(event).unwrap();
+        }
+        let policy = ReconnectPolicy::new(3);
+        let mut actions = vec![];
+        run(&clock, policy, &rx, |action| {
+            actions.push((action, clock.elapsed()));
+            actions.len() < count
+        });
+        actions
+    }
+
+    #[test]
+    fn recovered_during_backoff() {
+        // late recovery cancels the restart and resets attempts
+        let actions = actions(vec![Event::Failed, Event::Connected, Event::Failed], 1);
+        assert_eq!(actions, vec![(Action::Restart(1), Duration::from_secs(1))]);
+    }
+
+    #[test]
+    fn stalled_during_backoff() {
+        // stall restarts at once, the failure restart is dropped and its attempt stays spent
+        let actions = actions(vec![Event::Failed, Event::Stalled, Event::Failed], 2);
+        assert_eq!(
+            actions,
+            vec![
+                (Action::RestartStalled, Duration::ZERO),
+                (Action::Restart(2), Duration::from_secs(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_overflow() {
+        let policy = ReconnectPolicy::new(u32::MAX);
+        assert_eq!(policy.backoff(100), Some(Duration::from_secs(30)));
+    }
+}
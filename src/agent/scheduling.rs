@@ -0,0 +1,123 @@
+//! Name and scheduling priority of the libjuice threads running agent callbacks.
+//!
+//! libjuice doesn't expose its threads, so the options are applied from within the callbacks:
+//! on the first received packet or transition to [`crate::State::Connected`], whichever comes
+//! first on the thread. Earlier callbacks may run on the thread calling the agent methods and are
+//! skipped. A thread is configured once, by the first agent with options using it, so in
+//! [`crate::ConcurrencyMode::Poll`] and [`crate::ConcurrencyMode::Mux`] the options apply to a
+//! thread shared with other agents.
+use std::cell::Cell;
+
+/// Scheduling priority of the agent thread.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ThreadPriority {
+    /// Keep priority chosen by libjuice
+    #[default]
+    Normal,
+    /// Realtime (`SCHED_FIFO`) scheduling with given priority, 1 to 99 on Linux.
+    ///
+    /// Usually requires `CAP_SYS_NICE` or `RLIMIT_RTPRIO`, failure is logged and otherwise
+    /// ignored. Not supported on other platforms.
+    Realtime(u8),
+}
+
+/// Options applied to the agent thread.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct ThreadOptions {
+    pub(crate) name: Option<String>,
+    pub(crate) priority: ThreadPriority,
+}
+
+thread_local! {
+    static CONFIGURED: Cell<bool> = const { Cell::new(false) };
+}
+
+impl ThreadOptions {
+    /// Apply options to the current thread unless it was configured already
+    pub(crate) fn apply(&self) {
+        if CONFIGURED.with(|configured| configured.replace(true)) {
+            return;
+        }
+        if let Some(name) = &self.name {
+            set_name(name);
+        }
+        if let ThreadPriority::Realtime(priority) = self.priority {
+            set_realtime(priority);
+        }
+    }
+}
+
+/// Longest thread name accepted by Linux, without terminating nul
+#[cfg(target_os = "linux")]
+const MAX_NAME_LEN: usize = 15;
+
+#[cfg(target_os = "linux")]
+fn set_name(name: &str) {
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let name = match std::ffi::CString::new(&name[..end]) {
+        Ok(name) => name,
+        Err(_) => return log::warn!("invalid thread name {:?}", name),
+    };
+    let rc = unsafe { libc::pthread_setname_np(libc::pthread_self(), name.as_ptr()) };
+    if rc != 0 {
+        log::warn!(
+            "failed to set thread name: {}",
+            std::io::Error::from_raw_os_error(rc)
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime(priority: u8) {
+    let param = libc::sched_param {
+        sched_priority: priority.into(),
+    };
+    let rc = unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if rc != 0 {
+        log::warn!(
+            "failed to set realtime priority {}: {}",
+            priority,
+            std::io::Error::from_raw_os_error(rc)
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_name(name: &str) {
+    log::debug!("thread name {:?} not supported on this platform", name);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime(priority: u8) {
+    log::warn!(
+        "realtime priority {} not supported on this platform",
+        priority
+    );
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn apply_once() {
+        let options = ThreadOptions {
+            name: Some("juice-audio-io-thread".into()),
+            priority: ThreadPriority::Normal,
+        };
+        let other = ThreadOptions {
+            name: Some("other".into()),
+            ..options.clone()
+        };
+        let name = thread::spawn(move || {
+            options.apply();
+            other.apply();
+            std::fs::read_to_string("/proc/thread-self/comm").unwrap()
+        });
+        assert_eq!(name.join().unwrap().trim_end(), "juice-audio-io-");
+    }
+}
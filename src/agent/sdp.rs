@@ -0,0 +1,353 @@
+//! Session description helpers.
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use crate::agent::candidate::{Candidate, CandidateType};
+use crate::error::DescriptionError;
+
+/// ICE username fragment and password of the description, the first ones if repeated
+pub(crate) fn credentials(sdp: &str) -> Option<(String, String)> {
+    let mut ufrag = None;
+    let mut pwd = None;
+    for line in sdp.lines().map(str::trim) {
+        let attr = line.strip_prefix("a=").unwrap_or(line);
+        if let Some(value) = attr.strip_prefix("ice-ufrag:") {
+            ufrag.get_or_insert(value);
+        } else if let Some(value) = attr.strip_prefix("ice-pwd:") {
+            pwd.get_or_insert(value);
+        }
+    }
+    Some((ufrag?.to_string(), pwd?.to_string()))
+}
+
+/// Connection (`c=`) line of the local description.
+///
+/// libjuice doesn't generate one, but some SDP parsers pick the default candidate from it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DefaultCandidate {
+    /// Keep description as generated by libjuice
+    #[default]
+    Omit,
+    /// Add `c=IN IP4 0.0.0.0` placeholder
+    Placeholder,
+    /// Add address of the first host candidate, placeholder if there is none yet
+    FirstHost,
+}
+
+/// Validate remote description before passing it to libjuice.
+pub(crate) fn validate(sdp: &str) -> Result<(), DescriptionError> {
+    let mut ufrag = None;
+    let mut pwd = None;
+
+    for line in sdp.lines().map(str::trim) {
+        let attr = line.strip_prefix("a=").unwrap_or(line);
+        if let Some(value) = attr.strip_prefix("ice-ufrag:") {
+            if !is_ice_string(value, 4) {
+                return Err(DescriptionError::InvalidUfrag);
+            }
+            if ufrag.replace(value).is_some_and(|prev| prev != value) {
+                return Err(DescriptionError::ConflictingCredentials);
+            }
+        } else if let Some(value) = attr.strip_prefix("ice-pwd:") {
+            if !is_ice_string(value, 22) {
+                return Err(DescriptionError::InvalidPassword);
+            }
+            if pwd.replace(value).is_some_and(|prev| prev != value) {
+                return Err(DescriptionError::ConflictingCredentials);
+            }
+        } else if attr.starts_with("candidate:") {
+            let candidate = attr
+                .parse::<Candidate>()
+                .map_err(|_| DescriptionError::InvalidCandidate)?;
+            if !candidate.transport().eq_ignore_ascii_case("UDP") {
+                return Err(DescriptionError::UnsupportedTransport);
+            }
+        }
+    }
+
+    ufrag.ok_or(DescriptionError::MissingUfrag)?;
+    pwd.ok_or(DescriptionError::MissingPassword)?;
+    Ok(())
+}
+
+/// Prepend connection line to the local description
+pub(crate) fn add_connection_line(sdp: &str, default: DefaultCandidate) -> String {
+    let addr = match default {
+        DefaultCandidate::Omit => return sdp.to_string(),
+        DefaultCandidate::Placeholder => None,
+        DefaultCandidate::FirstHost => sdp
+            .lines()
+            .filter_map(|line| line.parse::<Candidate>().ok())
+            .filter(|c| c.kind() == CandidateType::Host)
+            .find_map(|c| c.address().parse::<IpAddr>().ok()),
+    };
+    let line = match addr {
+        Some(IpAddr::V6(ip)) => format!("c=IN IP6 {}", ip),
+        Some(IpAddr::V4(ip)) => format!("c=IN IP4 {}", ip),
+        None => "c=IN IP4 0.0.0.0".to_string(),
+    };
+    format!("{}\r\n{}", line, sdp)
+}
+
+/// Type, address, port and transport of a candidate
+pub(crate) type CandidateKey = (CandidateType, String, u16, String);
+
+/// Identity of a candidate, candidates differing only in foundation or priority are duplicates
+pub(crate) fn candidate_key(c: &Candidate) -> CandidateKey {
+    (
+        c.kind(),
+        c.address().to_string(),
+        c.port(),
+        c.transport().to_ascii_uppercase(),
+    )
+}
+
+/// Sort candidates by type, address family and descending priority, remove duplicates
+pub(crate) fn sort_candidates(candidates: &mut Vec<Candidate>) {
+    let type_rank = |c: &Candidate| match c.kind() {
+        CandidateType::Host => 0,
+        CandidateType::ServerReflexive => 1,
+        CandidateType::PeerReflexive => 2,
+        CandidateType::Relayed => 3,
+    };
+    let family_rank = |c: &Candidate| match c.address().parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 0,
+        Ok(IpAddr::V6(_)) => 1,
+        Err(_) => 2,
+    };
+    candidates.sort_by(|a, b| {
+        (
+            type_rank(a),
+            family_rank(a),
+            b.priority(),
+            a.address(),
+            a.port(),
+        )
+            .cmp(&(
+                type_rank(b),
+                family_rank(b),
+                a.priority(),
+                b.address(),
+                b.port(),
+            ))
+    });
+    let mut seen = HashSet::new();
+    candidates.retain(|c| seen.insert(candidate_key(c)));
+}
+
+/// Sort and deduplicate candidates of the description, they are placed where the first one was
+pub(crate) fn normalize(sdp: &str) -> String {
+    let mut candidates = vec![];
+    let mut lines = vec![];
+    let mut position = None;
+    for line in sdp.lines() {
+        match line.parse::<Candidate>() {
+            Ok(c) => {
+                position.get_or_insert(lines.len());
+                candidates.push(c);
+            }
+            Err(_) => lines.push(line),
+        }
+    }
+
+    sort_candidates(&mut candidates);
+    let position = position.unwrap_or(lines.len());
+    let sorted = candidates.iter().map(Candidate::as_sdp);
+    let mut out = lines[..position]
+        .iter()
+        .copied()
+        .chain(sorted)
+        .chain(lines[position..].iter().copied())
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    if sdp.ends_with('\n') {
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Check whether the line is a candidate attribute, parsable or not
+pub(crate) fn is_candidate_line(line: &str) -> bool {
+    let line = line.trim();
+    line.strip_prefix("a=")
+        .unwrap_or(line)
+        .starts_with("candidate:")
+}
+
+/// Remove candidates not passing the filter from the description.
+///
+/// Candidate lines which can't be parsed are removed too, libjuice accepts some of them.
+pub(crate) fn retain_candidates(sdp: &str, mut keep: impl FnMut(&Candidate) -> bool) -> String {
+    let mut out = sdp
+        .lines()
+        .filter(|line| match line.parse::<Candidate>() {
+            Ok(c) => keep(&c),
+            Err(_) => !is_candidate_line(line),
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    if sdp.ends_with('\n') {
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Replace priorities of candidates in the description or a single candidate line
+pub(crate) fn rewrite_priorities(sdp: &str, mut priority: impl FnMut(&Candidate) -> u32) -> String {
+    let mut out = sdp
+        .lines()
+        .map(|line| match line.parse::<Candidate>() {
+            Ok(c) => c.with_priority(priority(&c)).as_sdp().to_string(),
+            Err(_) => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n");
+    if sdp.ends_with('\n') {
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Check ice-char string of allowed length (RFC 8839)
+fn is_ice_string(s: &str, min_len: usize) -> bool {
+    (min_len..=256).contains(&s.len())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UFRAG: &str = "a=ice-ufrag:Ab+/";
+    const PWD: &str = "a=ice-pwd:0123456789abcdefghijkl";
+    const CANDIDATE: &str = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host";
+
+    #[test]
+    fn valid() {
+        let sdp = [UFRAG, PWD, "a=ice-options:ice2,trickle", CANDIDATE].join("\r\n");
+        assert_eq!(validate(&sdp), Ok(()));
+        // repeated on media level
+        let sdp = [UFRAG, PWD, "m=application 9 UDP", UFRAG, PWD].join("\n");
+        assert_eq!(validate(&sdp), Ok(()));
+    }
+
+    #[test]
+    fn connection_line() {
+        let sdp = [UFRAG, PWD, CANDIDATE, ""].join("\r\n");
+        assert_eq!(add_connection_line(&sdp, DefaultCandidate::Omit), sdp);
+        assert_eq!(
+            add_connection_line(&sdp, DefaultCandidate::Placeholder),
+            format!("c=IN IP4 0.0.0.0\r\n{}", sdp)
+        );
+        assert_eq!(
+            add_connection_line(&sdp, DefaultCandidate::FirstHost),
+            format!("c=IN IP4 192.168.1.5\r\n{}", sdp)
+        );
+
+        let sdp = [UFRAG, PWD, "a=candidate:1 1 UDP 1 fe80::1 9 typ host"].join("\r\n");
+        assert!(add_connection_line(&sdp, DefaultCandidate::FirstHost)
+            .starts_with("c=IN IP6 fe80::1\r\n"));
+        let sdp = [UFRAG, PWD].join("\r\n");
+        assert!(add_connection_line(&sdp, DefaultCandidate::FirstHost)
+            .starts_with("c=IN IP4 0.0.0.0\r\n"));
+    }
+
+    #[test]
+    fn normalize_candidates() {
+        let relay = "a=candidate:3 1 UDP 16777215 203.0.113.1 6000 typ relay";
+        let srflx = "a=candidate:2 1 UDP 1686052607 203.0.113.2 54321 typ srflx";
+        let host_v6 = "a=candidate:4 1 UDP 2122317823 fe80::1 54321 typ host";
+        let host_dup = "a=candidate:5 1 UDP 2122317800 192.168.1.5 54321 typ host";
+        let sdp = [UFRAG, relay, host_v6, CANDIDATE, srflx, host_dup, PWD, ""].join("\r\n");
+
+        let expected = [UFRAG, CANDIDATE, host_v6, srflx, relay, PWD, ""].join("\r\n");
+        assert_eq!(normalize(&sdp), expected);
+        assert_eq!(normalize(&expected), expected);
+        let sdp = [UFRAG, PWD].join("\r\n");
+        assert_eq!(normalize(&sdp), sdp);
+    }
+
+    #[test]
+    fn ice_credentials() {
+        let sdp = [UFRAG, CANDIDATE, PWD, ""].join("\r\n");
+        assert_eq!(
+            credentials(&sdp),
+            Some(("Ab+/".to_string(), "0123456789abcdefghijkl".to_string()))
+        );
+        assert_eq!(credentials(&[UFRAG, CANDIDATE].join("\r\n")), None);
+    }
+
+    #[test]
+    fn retain() {
+        let relay = "a=candidate:3 1 UDP 16777215 203.0.113.1 6000 typ relay";
+        let sdp = [UFRAG, CANDIDATE, relay, PWD, ""].join("\r\n");
+        let public = |c: &Candidate| c.kind() != CandidateType::Host;
+        assert_eq!(
+            retain_candidates(&sdp, public),
+            [UFRAG, relay, PWD, ""].join("\r\n")
+        );
+        assert_eq!(retain_candidates(&sdp, |_| true), sdp);
+
+        // out of range priority and port, parsed by libjuice regardless
+        let malformed = "a=candidate:1 1 UDP -1 10.0.0.1 70000 typ host";
+        let sdp = [UFRAG, malformed, relay, PWD, ""].join("\r\n");
+        assert_eq!(
+            retain_candidates(&sdp, |_| true),
+            [UFRAG, relay, PWD, ""].join("\r\n")
+        );
+    }
+
+    #[test]
+    fn priorities() {
+        let relay = "a=candidate:3 1 UDP 16777215 203.0.113.1 6000 typ relay";
+        let sdp = [UFRAG, relay, PWD, ""].join("\r\n");
+        let boost = |c: &Candidate| c.priority() + 1;
+        assert_eq!(
+            rewrite_priorities(&sdp, boost),
+            [
+                UFRAG,
+                "a=candidate:3 1 UDP 16777216 203.0.113.1 6000 typ relay",
+                PWD,
+                ""
+            ]
+            .join("\r\n")
+        );
+        assert_eq!(
+            rewrite_priorities(relay, |_| 1),
+            "a=candidate:3 1 UDP 1 203.0.113.1 6000 typ relay"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for (lines, err) in [
+            (vec![PWD], DescriptionError::MissingUfrag),
+            (vec![UFRAG], DescriptionError::MissingPassword),
+            (vec!["a=ice-ufrag:abc", PWD], DescriptionError::InvalidUfrag),
+            (
+                vec!["a=ice-ufrag:ab-c", PWD],
+                DescriptionError::InvalidUfrag,
+            ),
+            (
+                vec![UFRAG, "a=ice-pwd:short"],
+                DescriptionError::InvalidPassword,
+            ),
+            (
+                vec![UFRAG, PWD, "a=ice-ufrag:other"],
+                DescriptionError::ConflictingCredentials,
+            ),
+            (
+                vec![UFRAG, PWD, "a=candidate:1 1 UDP"],
+                DescriptionError::InvalidCandidate,
+            ),
+            (
+                vec![UFRAG, PWD, "a=candidate:1 1 TCP 1 192.168.1.5 9 typ host"],
+                DescriptionError::UnsupportedTransport,
+            ),
+        ] {
+            assert_eq!(validate(&lines.join("\n")), Err(err), "{:?}", lines);
+        }
+    }
+}
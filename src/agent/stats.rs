@@ -0,0 +1,83 @@
+//! Data path statistics.
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Agent data path counters.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// Packets successfully passed to libjuice
+    pub packets_sent: u64,
+    /// Bytes successfully passed to libjuice
+    pub bytes_sent: u64,
+    /// Packets delivered to the recv handler
+    pub packets_received: u64,
+    /// Bytes delivered to the recv handler
+    pub bytes_received: u64,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.packets_sent += rhs.packets_sent;
+        self.bytes_sent += rhs.bytes_sent;
+        self.packets_received += rhs.packets_received;
+        self.bytes_received += rhs.bytes_received;
+    }
+}
+
+/// Lock free counters updated from the data path.
+#[derive(Default)]
+pub(crate) struct Counters {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn on_send(&self, len: usize) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_recv(&self, len: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters() {
+        let counters = Counters::default();
+        counters.on_send(10);
+        counters.on_send(20);
+        counters.on_recv(5);
+
+        let mut stats = counters.snapshot();
+        assert_eq!(
+            stats,
+            Stats {
+                packets_sent: 2,
+                bytes_sent: 30,
+                packets_received: 1,
+                bytes_received: 5,
+            }
+        );
+
+        stats += counters.snapshot();
+        assert_eq!(stats.bytes_sent, 60);
+    }
+}
@@ -0,0 +1,160 @@
+//! Logical streams multiplexed over a single agent.
+//!
+//! Sub-channel packets carry a 3 bytes header: [`MARKER`] and big endian channel id. Once the
+//! first channel of an agent is opened, packets starting with [`MARKER`] are reserved and never
+//! reach the regular recv handler.
+//!
+//! # Example
+//! ```no_run
+//! # use libjuice_rs::{Agent, Handler};
+//! let agent = Agent::builder(Handler::default()).build().unwrap();
+//! let control = agent.channel(0).unwrap();
+//! let media = agent.channel(1).unwrap();
+//! control.set_recv_handler(|message| println!("control {:?}", message));
+//! media.set_recv_handler(|packet| println!("media {:?}", packet));
+//! // ... exchange descriptions and wait for connection
+//! control.send(b"hello").unwrap();
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::agent::{Holder, MAX_DATAGRAM_SIZE};
+use crate::{Error, Result};
+
+/// First byte of sub-channel packets
+pub const MARKER: u8 = 0xFB;
+const HEADER_SIZE: usize = 3;
+/// Largest message carried by a single packet
+pub const MAX_MESSAGE_SIZE: usize = MAX_DATAGRAM_SIZE - HEADER_SIZE;
+
+type RecvHandler = Arc<Mutex<Option<Box<dyn FnMut(&[u8]) + Send + 'static>>>>;
+
+/// Recv handlers of open channels.
+#[derive(Default)]
+pub(crate) struct Demux {
+    channels: Mutex<HashMap<u16, RecvHandler>>,
+}
+
+impl Demux {
+    /// Pass channel packet to its recv handler, returns false for other packets
+    pub(crate) fn dispatch(&self, packet: &[u8]) -> bool {
+        let (id, payload) = match packet {
+            [MARKER, hi, lo, payload @ ..] => (u16::from_be_bytes([*hi, *lo]), payload),
+            _ => return false,
+        };
+        let handler = self.channels.lock().unwrap().get(&id).cloned();
+        match handler {
+            Some(handler) => {
+                if let Some(f) = &mut *handler.lock().unwrap() {
+                    f(payload)
+                }
+            }
+            None => log::debug!("dropping packet of closed channel {}", id),
+        }
+        true
+    }
+
+    fn open(&self, id: u16) -> Result<RecvHandler> {
+        let mut channels = self.channels.lock().unwrap();
+        if channels.contains_key(&id) {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(channels.entry(id).or_default().clone())
+    }
+
+    fn close(&self, id: u16) {
+        self.channels.lock().unwrap().remove(&id);
+    }
+}
+
+/// Logical stream over the agent, see [`crate::Agent::channel`].
+///
+/// Closed on drop, packets for closed channels are dropped by the receiver.
+pub struct SubChannel {
+    id: u16,
+    holder: Weak<Holder>,
+    demux: Arc<Demux>,
+    on_recv: RecvHandler,
+}
+
+impl SubChannel {
+    pub(crate) fn open(holder: &Arc<Holder>, id: u16) -> Result<Self> {
+        let demux = holder.demux().clone();
+        let on_recv = demux.open(id)?;
+        Ok(Self {
+            id,
+            holder: Arc::downgrade(holder),
+            demux,
+            on_recv,
+        })
+    }
+
+    /// Get channel id
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Set handler of packets received on the channel, packets received before are dropped.
+    ///
+    /// Must not be called from the handler itself.
+    pub fn set_recv_handler<F>(&self, f: F)
+    where
+        F: FnMut(&[u8]),
+        F: Send + 'static,
+    {
+        *self.on_recv.lock().unwrap() = Some(Box::new(f));
+    }
+
+    /// Send packet to the channel of the same id on the remote agent
+    pub fn send(&self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(Error::MessageTooLarge);
+        }
+        let holder = self.holder.upgrade().ok_or(Error::NotAvailable)?;
+        let mut packet = Vec::with_capacity(HEADER_SIZE + data.len());
+        packet.push(MARKER);
+        packet.extend_from_slice(&self.id.to_be_bytes());
+        packet.extend_from_slice(data);
+        holder.send(&packet)
+    }
+}
+
+impl Drop for SubChannel {
+    fn drop(&mut self) {
+        self.demux.close(self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn dispatch() {
+        let demux = Demux::default();
+        let (tx, rx) = channel();
+        for id in [1, 0x0102] {
+            let tx = tx.clone();
+            *demux.open(id).unwrap().lock().unwrap() =
+                Some(Box::new(move |p: &[u8]| tx.send((id, p.to_vec())).unwrap()));
+        }
+        assert_eq!(demux.open(1).err(), Some(Error::InvalidArgument));
+
+        assert!(demux.dispatch(&[MARKER, 0, 1, 42]));
+        assert!(demux.dispatch(&[MARKER, 1, 2]));
+        // closed channel is consumed, other traffic is not
+        assert!(demux.dispatch(&[MARKER, 0, 7, 42]));
+        assert!(!demux.dispatch(&[0, 0, 1, 42]));
+        assert!(!demux.dispatch(&[MARKER, 0]));
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![(1, vec![42]), (0x0102, vec![])]
+        );
+
+        demux.close(1);
+        assert!(demux.dispatch(&[MARKER, 0, 1, 42]));
+        assert!(rx.try_recv().is_err());
+        assert!(demux.open(1).is_ok());
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use crate::agent::candidate::Candidate;
+use crate::agent::handler::Handler;
+use crate::agent::{Agent, Builder, State};
+
+type Shared<F> = Option<Arc<Mutex<Box<F>>>>;
+type StateHandler<T> = Shared<dyn FnMut(&T, State) + Send + 'static>;
+type CandidateHandler<T> = Shared<dyn FnMut(&T, String) + Send + 'static>;
+type CandidatesHandler<T> = Shared<dyn FnMut(&T, Vec<Candidate>) + Send + 'static>;
+type GatheringDoneHandler<T> = Shared<dyn FnMut(&T) + Send + 'static>;
+type RecvHandler<T> = Shared<dyn FnMut(&T, &[u8]) + Send + 'static>;
+
+/// Closures based event handler shared between several agents.
+///
+/// Every closure receives the tag of the agent which produced the event as the first argument,
+/// so a single set of closures can serve any number of agents. Per-agent [`Handler`] is created
+/// with [`TaggedHandler::handler`].
+///
+/// # Example
+/// ```
+/// # use libjuice_rs::{Agent, TaggedHandler};
+/// let h = TaggedHandler::default()
+///     .state_handler(|id: &u32, s| println!("Agent {} changed state to: {:?}", id, s))
+///     .recv_handler(|id: &u32, packet| println!("Agent {} received {} bytes", id, packet.len()));
+///
+/// let first = Agent::builder(h.handler(1));
+/// let second = Agent::builder(h.handler(2));
+/// ```
+pub struct TaggedHandler<T> {
+    /// ICE state change handler
+    on_state_change: StateHandler<T>,
+    /// Local ICE candidate handler
+    on_candidate: CandidateHandler<T>,
+    /// Local ICE candidates batch handler
+    on_candidates: CandidatesHandler<T>,
+    /// Gathering stage finish handler
+    on_gathering_done: GatheringDoneHandler<T>,
+    /// Incoming packet
+    on_recv: RecvHandler<T>,
+}
+
+impl<T> Default for TaggedHandler<T> {
+    fn default() -> Self {
+        Self {
+            on_state_change: None,
+            on_candidate: None,
+            on_candidates: None,
+            on_gathering_done: None,
+            on_recv: None,
+        }
+    }
+}
+
+impl<T> Clone for TaggedHandler<T> {
+    fn clone(&self) -> Self {
+        Self {
+            on_state_change: self.on_state_change.clone(),
+            on_candidate: self.on_candidate.clone(),
+            on_candidates: self.on_candidates.clone(),
+            on_gathering_done: self.on_gathering_done.clone(),
+            on_recv: self.on_recv.clone(),
+        }
+    }
+}
+
+impl<T> TaggedHandler<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Set ICE state change handler
+    pub fn state_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&T, State),
+        F: Send + 'static,
+    {
+        self.on_state_change = Some(Arc::new(Mutex::new(Box::new(f))));
+        self
+    }
+
+    /// Set local candidate handler
+    pub fn candidate_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&T, String),
+        F: Send + 'static,
+    {
+        self.on_candidate = Some(Arc::new(Mutex::new(Box::new(f))));
+        self
+    }
+
+    /// Set local candidates batch handler
+    pub fn candidates_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&T, Vec<Candidate>),
+        F: Send + 'static,
+    {
+        self.on_candidates = Some(Arc::new(Mutex::new(Box::new(f))));
+        self
+    }
+
+    /// Set gathering done handler
+    pub fn gathering_done_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&T),
+        F: Send + 'static,
+    {
+        self.on_gathering_done = Some(Arc::new(Mutex::new(Box::new(f))));
+        self
+    }
+
+    /// Set incoming packet handler
+    pub fn recv_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&T, &[u8]),
+        F: Send + 'static,
+    {
+        self.on_recv = Some(Arc::new(Mutex::new(Box::new(f))));
+        self
+    }
+
+    /// Create [`Handler`] for a single agent, every event of which will be reported with given tag.
+    pub fn handler(&self, tag: T) -> Handler {
+        let mut h = Handler::default();
+
+        if let Some(f) = self.on_state_change.clone() {
+            let tag = tag.clone();
+            h = h.state_handler(move |state| (f.lock().unwrap())(&tag, state));
+        }
+        if let Some(f) = self.on_candidate.clone() {
+            let tag = tag.clone();
+            h = h.candidate_handler(move |candidate| (f.lock().unwrap())(&tag, candidate));
+        }
+        if let Some(f) = self.on_candidates.clone() {
+            let tag = tag.clone();
+            h = h.candidates_handler(move |candidates| (f.lock().unwrap())(&tag, candidates));
+        }
+        if let Some(f) = self.on_gathering_done.clone() {
+            let tag = tag.clone();
+            h = h.gathering_done_handler(move || (f.lock().unwrap())(&tag));
+        }
+        if let Some(f) = self.on_recv.clone() {
+            h = h.recv_handler(move |packet| (f.lock().unwrap())(&tag, packet));
+        }
+
+        h
+    }
+
+    /// Create agent builder with [`TaggedHandler::handler`] for given tag, the tag is attached
+    /// as agent context as well (see [`Builder::with_context`])
+    pub fn builder(&self, tag: T) -> Builder
+    where
+        T: Debug,
+    {
+        Agent::builder(self.handler(tag.clone())).with_context(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn tags() {
+        let (tx, rx) = channel();
+        let tagged = TaggedHandler::default()
+            .state_handler({
+                let tx = tx.clone();
+                move |tag: &&str, state| tx.send(format!("{} {:?}", tag, state)).unwrap()
+            })
+            .recv_handler(move |tag, packet| tx.send(format!("{} {:?}", tag, packet)).unwrap());
+
+        let mut first = tagged.handler("first");
+        let mut second = tagged.handler("second");
+
+        first.on_state_changed(State::Gathering);
+        second.on_recv(&[1, 2], || unreachable!());
+        second.on_state_changed(State::Connected);
+        first.on_gathering_done();
+
+        assert_eq!(rx.try_recv().unwrap(), "first Gathering");
+        assert_eq!(rx.try_recv().unwrap(), "second [1, 2]");
+        assert_eq!(rx.try_recv().unwrap(), "second Connected");
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Local candidates batching.
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::agent::candidate::Candidate;
+use crate::agent::{sdp, Holder};
+use crate::clock::{self, Clock};
+
+/// Gathering events passed through the batcher
+pub(crate) enum Event {
+    Candidate(Candidate),
+    GatheringDone,
+}
+
+/// Batcher loop, lives until the agent is dropped.
+///
+/// Collects candidates arriving within `window` after the first one of a batch. Gathering done
+/// event flushes the pending batch immediately and is delivered right after it.
+pub(crate) fn batch(
+    holder: Weak<Holder>,
+    clock: Arc<dyn Clock>,
+    window: Duration,
+    events: Receiver<Event>,
+) {
+    while let Some((mut candidates, done)) = collect(&*clock, window, &events) {
+        let holder = match holder.upgrade() {
+            Some(holder) => holder,
+            None => break,
+        };
+
+        if holder.surfaced.is_some() {
+            sdp::sort_candidates(&mut candidates);
+        }
+        let mut h = holder.handler.lock();
+        if !candidates.is_empty() {
+            h.on_candidates(candidates);
+        }
+        if done {
+            h.on_gathering_done();
+        }
+    }
+}
+
+/// Wait for the next batch, returns its candidates and whether gathering is done
+fn collect(
+    clock: &dyn Clock,
+    window: Duration,
+    events: &Receiver<Event>,
+) -> Option<(Vec<Candidate>, bool)> {
+    let mut candidates = vec![];
+    let mut done = false;
+
+    match events.recv().ok()? {
+        Event::Candidate(c) => candidates.push(c),
+        Event::GatheringDone => done = true,
+    }
+
+    let deadline = clock.now() + window;
+    while !done {
+        let timeout = deadline.saturating_duration_since(clock.now());
+        match clock::recv_timeout(clock, events, timeout) {
+            Ok(Event::Candidate(c)) => candidates.push(c),
+            Ok(Event::GatheringDone) => done = true,
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    Some((candidates, done))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn window() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel();
+        let candidate: Candidate = "a=candidate:1 1 UDP 2122317823 192.168.1.5 5000 typ host"
+            .parse()
+            .unwrap();
+
+        tx.send(Event::Candidate(candidate.clone())).unwrap();
+        tx.send(Event::Candidate(candidate.clone())).unwrap();
+        let window = Duration::from_millis(100);
+        let (candidates, done) = collect(&clock, window, &rx).unwrap();
+        assert_eq!((candidates.len(), done), (2, false));
+        assert_eq!(clock.elapsed(), window);
+
+        // gathering done flushes without waiting for the window
+        tx.send(Event::Candidate(candidate)).unwrap();
+        tx.send(Event::GatheringDone).unwrap();
+        let (candidates, done) = collect(&clock, window, &rx).unwrap();
+        assert_eq!((candidates.len(), done), (1, true));
+        assert_eq!(clock.elapsed(), window);
+
+        drop(tx);
+        assert!(collect(&clock, window, &rx).is_none());
+    }
+}
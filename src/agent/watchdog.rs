@@ -0,0 +1,168 @@
+//! Relay fallback on silent direct path.
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::agent::candidate::{Candidate, CandidateType};
+use crate::agent::{reconnect, Holder, State};
+use crate::clock::{self, Clock};
+
+/// Minimal period of path checks
+const MIN_CHECK_PERIOD: Duration = Duration::from_millis(100);
+
+/// Data path activity tracking.
+pub(crate) struct Activity {
+    clock: Arc<dyn Clock>,
+    epoch: Instant,
+    /// Last receive time, milliseconds since epoch
+    last_recv: AtomicU64,
+    /// Last send time, milliseconds since epoch
+    last_send: AtomicU64,
+    /// Whether local relayed candidate was gathered
+    has_relay: AtomicBool,
+}
+
+impl Activity {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            epoch: clock.now(),
+            clock,
+            last_recv: AtomicU64::new(0),
+            last_send: AtomicU64::new(0),
+            has_relay: AtomicBool::new(false),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.now().duration_since(self.epoch).as_millis() as u64
+    }
+
+    /// Mark the data path as alive
+    pub(crate) fn touch(&self) {
+        self.last_recv.store(self.now(), Ordering::Relaxed);
+    }
+
+    /// Mark outgoing application traffic
+    pub(crate) fn on_send(&self) {
+        self.last_send.store(self.now(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_candidate(&self, candidate: &Candidate) {
+        if candidate.kind() == CandidateType::Relayed {
+            self.has_relay.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Reset after agent restart
+    pub(crate) fn reset(&self) {
+        self.last_recv.store(self.now(), Ordering::Relaxed);
+        self.last_send.store(0, Ordering::Relaxed);
+        self.has_relay.store(false, Ordering::Relaxed);
+    }
+
+    /// Time since last received packet
+    pub(crate) fn silence(&self) -> Duration {
+        let last = self.last_recv.load(Ordering::Relaxed);
+        Duration::from_millis(self.now().saturating_sub(last))
+    }
+
+    /// Whether application sent packets within `period`, i.e. replies are expected
+    pub(crate) fn is_sending(&self, period: Duration) -> bool {
+        let last = self.last_send.load(Ordering::Relaxed);
+        last != 0 && self.now().saturating_sub(last) < period.as_millis() as u64
+    }
+}
+
+/// Watchdog loop, lives until the agent is dropped.
+///
+/// Fires `on_fallback` once per silence period when the selected pair is a direct one, no packet
+/// was received for `timeout` while the application kept sending, and a relayed candidate is
+/// available. The agent is then restarted if reconnect policy is set.
+pub(crate) fn watch(
+    holder: Weak<Holder>,
+    clock: Arc<dyn Clock>,
+    timeout: Duration,
+    mut on_fallback: Box<dyn FnMut() + Send + 'static>,
+    alive: Receiver<()>,
+) {
+    let period = std::cmp::max(timeout / 4, MIN_CHECK_PERIOD);
+    let mut fired = false;
+
+    while let Err(RecvTimeoutError::Timeout) = clock::recv_timeout(&*clock, &alive, period) {
+        let holder = match holder.upgrade() {
+            Some(holder) => holder,
+            None => break,
+        };
+
+        // idle path is healthy, libjuice keepalives are not visible here
+        let silence = holder.activity.silence();
+        if silence < timeout || !holder.activity.is_sending(timeout) {
+            fired = false;
+            continue;
+        }
+        if fired || !holder.activity.has_relay.load(Ordering::Relaxed) {
+            continue;
+        }
+        if !matches!(holder.state(), State::Connected | State::Completed) {
+            continue;
+        }
+
+        let direct = match holder.selected_candidates() {
+            Ok((local, _)) => local
+                .parse::<Candidate>()
+                .map(|c| c.kind() != CandidateType::Relayed)
+                .unwrap_or(false),
+            Err(_) => false,
+        };
+        if !direct {
+            continue;
+        }
+
+        log::warn!(
+            "{}no packets on direct path for {:?}, falling back to relay",
+            holder.label(),
+            silence
+        );
+        fired = true;
+        on_fallback();
+        let _ = holder.notify_supervisor(reconnect::Event::Stalled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn activity() {
+        let clock = Arc::new(MockClock::new());
+        let activity = Activity::new(clock.clone());
+        let candidate: Candidate = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host"
+            .parse()
+            .unwrap();
+        activity.on_candidate(&candidate);
+        assert!(!activity.has_relay.load(Ordering::Relaxed));
+
+        let candidate: Candidate = "a=candidate:3 1 UDP 16777215 203.0.113.1 6000 typ relay"
+            .parse()
+            .unwrap();
+        activity.on_candidate(&candidate);
+        assert!(activity.has_relay.load(Ordering::Relaxed));
+
+        activity.touch();
+        clock.sleep(Duration::from_secs(5));
+        assert_eq!(activity.silence(), Duration::from_secs(5));
+        assert!(!activity.is_sending(Duration::from_secs(5)));
+
+        activity.on_send();
+        clock.sleep(Duration::from_secs(1));
+        assert!(activity.is_sending(Duration::from_secs(5)));
+        clock.sleep(Duration::from_secs(4));
+        assert!(!activity.is_sending(Duration::from_secs(5)));
+
+        activity.reset();
+        assert!(!activity.has_relay.load(Ordering::Relaxed));
+    }
+}
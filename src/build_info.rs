@@ -0,0 +1,124 @@
+//! Build configuration of the linked libjuice.
+use std::fmt::{Display, Formatter};
+
+use libjuice_sys as sys;
+
+/// libjuice compile-time options.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BuildInfo {
+    /// Embedded STUN/TURN server is available
+    pub server: bool,
+    /// Nettle is used for hashing
+    pub nettle: bool,
+    /// Candidates are gathered on loopback addresses
+    pub localhost_address: bool,
+    /// Local addresses are translated to localhost when possible
+    pub local_address_translation: bool,
+    /// Built for a mobile (Android or iOS) target
+    pub mobile: bool,
+}
+
+/// Get compile-time options of the linked libjuice
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        server: sys::BUILD_SERVER,
+        nettle: sys::BUILD_NETTLE,
+        localhost_address: sys::BUILD_LOCALHOST_ADDRESS,
+        local_address_translation: sys::BUILD_LOCAL_ADDRESS_TRANSLATION,
+        mobile: cfg!(mobile),
+    }
+}
+
+/// libjuice version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LibjuiceVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl LibjuiceVersion {
+    /// Parse "major.minor[.patch]" version, suffixes like "-dev" are ignored
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.split(|c: char| c != '.' && !c.is_ascii_digit()).next()?;
+        let mut parts = s.split('.').map(str::parse::<u32>);
+        Some(Self {
+            major: parts.next()?.ok()?,
+            minor: parts.next()?.ok()?,
+            patch: parts.next().unwrap_or(Ok(0)).ok()?,
+        })
+    }
+}
+
+impl Display for LibjuiceVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Get version of the linked libjuice, `None` if unknown, i.e. installed library was found
+/// without pkg-config
+pub fn version() -> Option<LibjuiceVersion> {
+    sys::LIBJUICE_VERSION.and_then(LibjuiceVersion::parse)
+}
+
+/// Hashing backend used for STUN message integrity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CryptoBackend {
+    /// libjuice own implementation
+    Builtin,
+    Nettle,
+}
+
+/// Runtime capabilities of the linked libjuice.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Capabilities {
+    /// Embedded STUN/TURN server is available
+    pub server: bool,
+    /// TCP candidates are supported, libjuice is UDP only
+    pub tcp: bool,
+    pub crypto: CryptoBackend,
+    /// Whether the report is exact: bundled libjuice is built with the enabled features, while
+    /// the options of an installed one are assumed
+    pub exact: bool,
+}
+
+/// Get capabilities of the linked libjuice
+pub fn capabilities() -> Capabilities {
+    let info = build_info();
+    Capabilities {
+        server: info.server,
+        tcp: false,
+        crypto: if info.nettle {
+            CryptoBackend::Nettle
+        } else {
+            CryptoBackend::Builtin
+        },
+        exact: sys::BUILD_BUNDLED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version() {
+        let version = |major, minor, patch| LibjuiceVersion {
+            major,
+            minor,
+            patch,
+        };
+        assert_eq!(LibjuiceVersion::parse("1.2.3"), Some(version(1, 2, 3)));
+        assert_eq!(LibjuiceVersion::parse("1.5"), Some(version(1, 5, 0)));
+        assert_eq!(LibjuiceVersion::parse("1.4.0-dev"), Some(version(1, 4, 0)));
+        assert_eq!(LibjuiceVersion::parse("1"), None);
+        assert_eq!(LibjuiceVersion::parse("x.y"), None);
+        assert!(version(1, 10, 0) > version(1, 9, 5));
+        assert_eq!(version(1, 2, 3).to_string(), "1.2.3");
+    }
+}
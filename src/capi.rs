@@ -0,0 +1,463 @@
+//! C API.
+//!
+//! Exposes [`Agent`] lifecycle to non-Rust applications. No callbacks cross the FFI boundary,
+//! events are queued and retrieved with [`juicers_agent_poll`]. Build a C library with
+//! `cargo rustc --release --features capi --crate-type staticlib` (or `cdylib`), the header can
+//! be generated with `cbindgen --lang c`.
+//!
+//! Functions return 0 on success or one of `JUICERS_ERR_*` codes. Error values match libjuice
+//! `JUICE_ERR_*` codes where those exist.
+use std::ffi::CStr;
+use std::net::IpAddr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Agent, Error, Handler, Result, State};
+
+pub const JUICERS_ERR_SUCCESS: c_int = 0;
+pub const JUICERS_ERR_INVALID: c_int = -1;
+pub const JUICERS_ERR_FAILED: c_int = -2;
+pub const JUICERS_ERR_NOT_AVAIL: c_int = -3;
+/// Data doesn't fit into a packet or given buffer
+pub const JUICERS_ERR_TOO_LARGE: c_int = -4;
+/// No event within timeout
+pub const JUICERS_ERR_TIMEOUT: c_int = -5;
+
+/// Maximum number of events waiting for [`juicers_agent_poll`]
+const QUEUE_SIZE: usize = 1024;
+
+fn error_code(e: Error) -> c_int {
+    match e {
+        Error::InvalidArgument
+        | Error::InvalidDescription(_)
+        | Error::InvalidState(..)
+        | Error::AddressRejected => JUICERS_ERR_INVALID,
+        Error::Failed => JUICERS_ERR_FAILED,
+        Error::NotAvailable | Error::UnsupportedMode(_) => JUICERS_ERR_NOT_AVAIL,
+        Error::MessageTooLarge => JUICERS_ERR_TOO_LARGE,
+        Error::Timeout => JUICERS_ERR_TIMEOUT,
+    }
+}
+
+fn result_code(result: Result<()>) -> c_int {
+    result.map_or_else(error_code, |_| JUICERS_ERR_SUCCESS)
+}
+
+/// Agent configuration, zeroed fields mean defaults.
+#[repr(C)]
+pub struct JuicersConfig {
+    /// STUN server host, default is "stun.l.google.com"
+    pub stun_server_host: *const c_char,
+    pub stun_server_port: u16,
+    /// Local address to bind to
+    pub bind_address: *const c_char,
+    pub local_port_range_begin: u16,
+    pub local_port_range_end: u16,
+}
+
+/// Type of [`JuicersEvent`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JuicersEventType {
+    /// ICE state changed, see `state`
+    StateChanged = 0,
+    /// Local candidate, nul-terminated sdp is written to the buffer
+    Candidate = 1,
+    /// Local candidates gathering finished
+    GatheringDone = 2,
+    /// Packet received, payload is written to the buffer
+    Recv = 3,
+}
+
+/// Event retrieved by [`juicers_agent_poll`].
+#[repr(C)]
+pub struct JuicersEvent {
+    pub kind: JuicersEventType,
+    /// New state, same values as libjuice `juice_state_t`
+    pub state: c_int,
+    /// Bytes written to the buffer, or required buffer size on [`JUICERS_ERR_TOO_LARGE`]
+    pub size: usize,
+}
+
+enum Event {
+    StateChanged(State),
+    Candidate(String),
+    GatheringDone,
+    Recv(Vec<u8>),
+}
+
+impl Event {
+    /// Fill `out` and copy event data into `buffer`
+    fn write(&self, out: &mut JuicersEvent, buffer: &mut [u8]) -> c_int {
+        let (kind, data) = match self {
+            Event::StateChanged(state) => {
+                out.state = *state as c_int;
+                (JuicersEventType::StateChanged, &[][..])
+            }
+            Event::Candidate(sdp) => (JuicersEventType::Candidate, sdp.as_bytes()),
+            Event::GatheringDone => (JuicersEventType::GatheringDone, &[][..]),
+            Event::Recv(packet) => (JuicersEventType::Recv, &packet[..]),
+        };
+        out.kind = kind;
+
+        let nul = usize::from(kind == JuicersEventType::Candidate);
+        out.size = data.len() + nul;
+        if out.size > buffer.len() {
+            return JUICERS_ERR_TOO_LARGE;
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        if nul == 1 {
+            buffer[data.len()] = 0;
+        }
+        JUICERS_ERR_SUCCESS
+    }
+}
+
+/// Sending side of the event queue.
+#[derive(Clone)]
+struct Queue {
+    tx: SyncSender<Event>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Queue {
+    /// Queue event, waits for [`juicers_agent_poll`] if the queue is full
+    fn push(&self, event: Event) {
+        let _ = self.tx.send(event);
+    }
+
+    /// Queue received packet, dropped if the queue is full
+    fn push_packet(&self, packet: &[u8]) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(Event::Recv(packet.to_vec())) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Opaque agent handle.
+pub struct JuicersAgent {
+    /// Event queue and the event which didn't fit into the caller buffer, dropped before the
+    /// agent to release callbacks waiting for a full queue
+    events: Mutex<(Receiver<Event>, Option<Event>)>,
+    /// Received packets dropped because the queue was full
+    dropped: Arc<AtomicU64>,
+    agent: Agent,
+}
+
+unsafe fn opt_str<'a>(s: *const c_char) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    let s = CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::InvalidArgument)?;
+    Ok(Some(s))
+}
+
+unsafe fn string(s: *const c_char) -> Result<String> {
+    opt_str(s)?
+        .map(str::to_string)
+        .ok_or(Error::InvalidArgument)
+}
+
+unsafe fn create(config: Option<&JuicersConfig>) -> Result<JuicersAgent> {
+    let (tx, rx) = sync_channel(QUEUE_SIZE);
+    let queue = Queue {
+        tx,
+        dropped: Arc::new(AtomicU64::new(0)),
+    };
+    let dropped = queue.dropped.clone();
+    let handler = Handler::default()
+        .state_handler({
+            let queue = queue.clone();
+            move |state| queue.push(Event::StateChanged(state))
+        })
+        .candidate_handler({
+            let queue = queue.clone();
+            move |sdp| queue.push(Event::Candidate(sdp))
+        })
+        .gathering_done_handler({
+            let queue = queue.clone();
+            move || queue.push(Event::GatheringDone)
+        })
+        .recv_handler(move |packet| queue.push_packet(packet));
+
+    let mut builder = Agent::builder(handler);
+    if let Some(config) = config {
+        if let Some(host) = opt_str(config.stun_server_host)? {
+            builder = builder.with_stun(host.to_string(), config.stun_server_port);
+        }
+        if let Some(addr) = opt_str(config.bind_address)? {
+            let addr = addr.parse::<IpAddr>().map_err(|_| Error::InvalidArgument)?;
+            builder = builder.with_bind_address(&addr);
+        }
+        if config.local_port_range_begin != 0 || config.local_port_range_end != 0 {
+            builder =
+                builder.with_port_range(config.local_port_range_begin, config.local_port_range_end);
+        }
+    }
+
+    Ok(JuicersAgent {
+        agent: builder.build()?,
+        events: Mutex::new((rx, None)),
+        dropped,
+    })
+}
+
+/// Create agent, returns null on failure.
+///
+/// # Safety
+/// `config` must be null or point to a valid config, strings in it must be null or
+/// nul-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_create(config: *const JuicersConfig) -> *mut JuicersAgent {
+    match create(config.as_ref()) {
+        Ok(agent) => Box::into_raw(Box::new(agent)),
+        Err(e) => {
+            log::error!("failed to create agent: {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Destroy agent.
+///
+/// # Safety
+/// `agent` must be null or returned by [`juicers_agent_create`] and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_destroy(agent: *mut JuicersAgent) {
+    if !agent.is_null() {
+        drop(Box::from_raw(agent));
+    }
+}
+
+/// Get ICE state, same values as libjuice `juice_state_t`.
+///
+/// # Safety
+/// `agent` must be a valid agent.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_get_state(agent: *const JuicersAgent) -> c_int {
+    (*agent).agent.get_state() as c_int
+}
+
+/// Get number of received packets dropped because [`juicers_agent_poll`] wasn't called often
+/// enough.
+///
+/// # Safety
+/// `agent` must be a valid agent.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_get_dropped_packets(agent: *const JuicersAgent) -> u64 {
+    (*agent).dropped.load(Ordering::Relaxed)
+}
+
+/// Start ICE candidates gathering.
+///
+/// # Safety
+/// `agent` must be a valid agent.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_gather(agent: *const JuicersAgent) -> c_int {
+    result_code((*agent).agent.gather_candidates())
+}
+
+/// Write nul-terminated local description into `buffer` of `size` bytes.
+///
+/// # Safety
+/// `agent` must be a valid agent, `buffer` must be valid for writes of `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_get_local_description(
+    agent: *const JuicersAgent,
+    buffer: *mut c_char,
+    size: usize,
+) -> c_int {
+    let sdp = match (*agent).agent.get_local_description() {
+        Ok(sdp) => sdp,
+        Err(e) => return error_code(e),
+    };
+    if sdp.len() >= size {
+        return JUICERS_ERR_TOO_LARGE;
+    }
+    ptr::copy_nonoverlapping(sdp.as_ptr(), buffer as *mut u8, sdp.len());
+    *buffer.add(sdp.len()) = 0;
+    JUICERS_ERR_SUCCESS
+}
+
+/// Set remote description.
+///
+/// # Safety
+/// `agent` must be a valid agent, `sdp` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_set_remote_description(
+    agent: *const JuicersAgent,
+    sdp: *const c_char,
+) -> c_int {
+    result_code(string(sdp).and_then(|sdp| (*agent).agent.set_remote_description(sdp)))
+}
+
+/// Add remote candidate.
+///
+/// # Safety
+/// `agent` must be a valid agent, `sdp` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_add_remote_candidate(
+    agent: *const JuicersAgent,
+    sdp: *const c_char,
+) -> c_int {
+    result_code(string(sdp).and_then(|sdp| (*agent).agent.add_remote_candidate(sdp)))
+}
+
+/// Signal remote candidates exhausted.
+///
+/// # Safety
+/// `agent` must be a valid agent.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_set_remote_gathering_done(
+    agent: *const JuicersAgent,
+) -> c_int {
+    result_code((*agent).agent.set_remote_gathering_done())
+}
+
+/// Send packet to remote endpoint.
+///
+/// # Safety
+/// `agent` must be a valid agent, `data` must be valid for reads of `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_send(
+    agent: *const JuicersAgent,
+    data: *const u8,
+    size: usize,
+) -> c_int {
+    if data.is_null() {
+        return JUICERS_ERR_INVALID;
+    }
+    result_code((*agent).agent.send(slice::from_raw_parts(data, size)))
+}
+
+/// Wait up to `timeout_ms` milliseconds for the next event, negative timeout waits forever.
+///
+/// Candidate sdp and received packets are copied into `buffer` of `size` bytes. If the event
+/// data doesn't fit, [`JUICERS_ERR_TOO_LARGE`] is returned with the required size and the same
+/// event is returned by the next call.
+///
+/// Up to 1024 events are queued. When the queue is full, received packets are dropped and
+/// counted by [`juicers_agent_get_dropped_packets`], other events wait until polled, which
+/// stalls the agent thread.
+///
+/// # Safety
+/// `agent` must be a valid agent, `event` must be valid for writes, `buffer` must be valid for
+/// writes of `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn juicers_agent_poll(
+    agent: *const JuicersAgent,
+    event: *mut JuicersEvent,
+    buffer: *mut u8,
+    size: usize,
+    timeout_ms: c_int,
+) -> c_int {
+    if event.is_null() || (buffer.is_null() && size > 0) {
+        return JUICERS_ERR_INVALID;
+    }
+    let mut events = (*agent).events.lock().unwrap();
+    let (rx, pending) = &mut *events;
+
+    let next = match pending.take() {
+        Some(next) => next,
+        None if timeout_ms < 0 => match rx.recv() {
+            Ok(next) => next,
+            Err(_) => return JUICERS_ERR_FAILED,
+        },
+        None => match rx.recv_timeout(Duration::from_millis(timeout_ms as u64)) {
+            Ok(next) => next,
+            Err(RecvTimeoutError::Timeout) => return JUICERS_ERR_TIMEOUT,
+            Err(RecvTimeoutError::Disconnected) => return JUICERS_ERR_FAILED,
+        },
+    };
+
+    let buffer = if size == 0 {
+        &mut [][..]
+    } else {
+        slice::from_raw_parts_mut(buffer, size)
+    };
+    let ret = next.write(&mut *event, buffer);
+    if ret == JUICERS_ERR_TOO_LARGE {
+        *pending = Some(next);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConcurrencyMode;
+
+    fn empty() -> JuicersEvent {
+        JuicersEvent {
+            kind: JuicersEventType::GatheringDone,
+            state: 0,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn write_event() {
+        let mut out = empty();
+        let mut buffer = [0xff; 8];
+
+        let ret = Event::StateChanged(State::Connected).write(&mut out, &mut buffer);
+        assert_eq!(ret, JUICERS_ERR_SUCCESS);
+        assert_eq!(out.kind, JuicersEventType::StateChanged);
+        assert_eq!(out.state, 3);
+        assert_eq!(out.size, 0);
+
+        let ret = Event::Candidate("a=x".into()).write(&mut out, &mut buffer);
+        assert_eq!(ret, JUICERS_ERR_SUCCESS);
+        assert_eq!(out.kind, JuicersEventType::Candidate);
+        assert_eq!(&buffer[..out.size], b"a=x\0");
+
+        let ret = Event::Recv(vec![1; 9]).write(&mut out, &mut buffer);
+        assert_eq!(ret, JUICERS_ERR_TOO_LARGE);
+        assert_eq!(out.kind, JuicersEventType::Recv);
+        assert_eq!(out.size, 9);
+    }
+
+    #[test]
+    fn queue_overflow() {
+        let (tx, rx) = sync_channel(2);
+        let queue = Queue {
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        queue.push(Event::GatheringDone);
+        queue.push_packet(&[1]);
+        queue.push_packet(&[2]);
+        queue.push_packet(&[3]);
+        assert_eq!(queue.dropped.load(Ordering::Relaxed), 2);
+
+        assert!(matches!(rx.try_recv(), Ok(Event::GatheringDone)));
+        assert!(matches!(rx.try_recv(), Ok(Event::Recv(p)) if p == [1]));
+        queue.push_packet(&[4]);
+        assert!(matches!(rx.try_recv(), Ok(Event::Recv(p)) if p == [4]));
+        assert_eq!(queue.dropped.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(
+            error_code(Error::InvalidArgument),
+            libjuice_sys::JUICE_ERR_INVALID
+        );
+        assert_eq!(error_code(Error::Failed), libjuice_sys::JUICE_ERR_FAILED);
+        assert_eq!(
+            error_code(Error::NotAvailable),
+            libjuice_sys::JUICE_ERR_NOT_AVAIL
+        );
+        assert_eq!(
+            error_code(Error::UnsupportedMode(ConcurrencyMode::Mux)),
+            libjuice_sys::JUICE_ERR_NOT_AVAIL
+        );
+    }
+}
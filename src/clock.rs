@@ -0,0 +1,118 @@
+//! Time source of wrapper-side timers.
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Clock used by reconnect backoff, relay fallback watchdog, trickle batching, pacing and
+/// estimation.
+///
+/// Replaced with a simulated clock in tests to fast-forward timeouts.
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    /// Whether time advances only by [`Clock::sleep`], waits don't block then
+    fn is_simulated(&self) -> bool {
+        false
+    }
+}
+
+/// Real time.
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Wait for a message up to `timeout` of the clock time.
+///
+/// Simulated clock doesn't block, it takes a pending message or advances time by `timeout`.
+pub(crate) fn recv_timeout<T>(
+    clock: &dyn Clock,
+    rx: &Receiver<T>,
+    timeout: Duration,
+) -> Result<T, RecvTimeoutError> {
+    if !clock.is_simulated() {
+        return rx.recv_timeout(timeout);
+    }
+    match rx.try_recv() {
+        Ok(message) => Ok(message),
+        Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+        Err(TryRecvError::Empty) => {
+            clock.sleep(timeout);
+            Err(RecvTimeoutError::Timeout)
+        }
+    }
+}
+
+/// Simulated clock, advanced by sleeps only.
+#[cfg(test)]
+pub(crate) struct MockClock {
+    start: Instant,
+    elapsed: std::sync::Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Default::default(),
+        }
+    }
+
+    /// Time passed since creation
+    pub(crate) fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    fn is_simulated(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn simulated_wait() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel();
+
+        tx.send(1).unwrap();
+        assert_eq!(recv_timeout(&clock, &rx, Duration::from_secs(5)), Ok(1));
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        assert_eq!(
+            recv_timeout(&clock, &rx, Duration::from_secs(5)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert_eq!(clock.elapsed(), Duration::from_secs(5));
+
+        drop(tx);
+        assert_eq!(
+            recv_timeout(&clock, &rx, Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -1,5 +1,8 @@
 use std::fmt::{Display, Formatter};
 
+use crate::agent::negotiation::NegotiationState;
+use crate::agent::ConcurrencyMode;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -7,6 +10,32 @@ pub enum Error {
     InvalidArgument,
     Failed,
     NotAvailable,
+    /// Remote description rejected by strict signaling checks
+    InvalidDescription(DescriptionError),
+    /// Packet doesn't fit into a single datagram
+    MessageTooLarge,
+    /// Signaling call out of order, (expected, actual) negotiation state
+    InvalidState(NegotiationState, NegotiationState),
+    /// Remote candidate address denied by the remote address policy
+    AddressRejected,
+    /// Operation not finished in time
+    Timeout,
+    /// Concurrency mode not supported by the linked libjuice
+    UnsupportedMode(ConcurrencyMode),
+}
+
+/// Reason of remote description rejection.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DescriptionError {
+    MissingUfrag,
+    MissingPassword,
+    InvalidUfrag,
+    InvalidPassword,
+    /// Different credentials given in the same description
+    ConflictingCredentials,
+    InvalidCandidate,
+    /// Non-UDP candidate, libjuice supports only UDP
+    UnsupportedTransport,
 }
 
 impl std::error::Error for Error {}
@@ -17,6 +46,30 @@ impl Display for Error {
             Error::InvalidArgument => write!(f, "invalid argument"),
             Error::Failed => write!(f, "failure"),
             Error::NotAvailable => write!(f, "not available"),
+            Error::InvalidDescription(e) => write!(f, "invalid remote description: {}", e),
+            Error::MessageTooLarge => write!(f, "message too large"),
+            Error::InvalidState(expected, actual) => write!(
+                f,
+                "invalid negotiation state: expected {:?}, actual {:?}",
+                expected, actual
+            ),
+            Error::AddressRejected => write!(f, "remote address rejected by policy"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::UnsupportedMode(mode) => write!(f, "unsupported concurrency mode {:?}", mode),
+        }
+    }
+}
+
+impl Display for DescriptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DescriptionError::MissingUfrag => write!(f, "missing ice-ufrag"),
+            DescriptionError::MissingPassword => write!(f, "missing ice-pwd"),
+            DescriptionError::InvalidUfrag => write!(f, "malformed ice-ufrag"),
+            DescriptionError::InvalidPassword => write!(f, "malformed ice-pwd"),
+            DescriptionError::ConflictingCredentials => write!(f, "conflicting ice credentials"),
+            DescriptionError::InvalidCandidate => write!(f, "malformed candidate"),
+            DescriptionError::UnsupportedTransport => write!(f, "non-UDP candidate"),
         }
     }
 }
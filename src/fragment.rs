@@ -0,0 +1,184 @@
+//! Fragmentation of messages exceeding a single datagram.
+//!
+//! Every fragment starts with 4 bytes header: message id (u16, big endian), fragment index and
+//! fragments count. Fragments of both sides have to be produced and consumed by this module.
+//!
+//! # Example
+//! ```no_run
+//! # use std::sync::Mutex;
+//! # use libjuice_rs::{Agent, Handler};
+//! # use libjuice_rs::fragment::{Fragmenter, Reassembler};
+//! let reassembler = Mutex::new(Reassembler::default());
+//! let handler = Handler::default().recv_handler(move |packet| {
+//!     if let Some(message) = reassembler.lock().unwrap().push(packet) {
+//!         println!("received message of {} bytes", message.len());
+//!     }
+//! });
+//! let agent = Agent::builder(handler).build().unwrap();
+//! // ... exchange descriptions and wait for connection
+//! let mut fragmenter = Fragmenter::new(agent.mtu().unwrap());
+//! let fragments = fragmenter.split(&[0u8; 10000]).unwrap();
+//! for fragment in &fragments {
+//!     agent.send(fragment).unwrap();
+//! }
+//! ```
+use std::collections::{HashMap, VecDeque};
+
+use crate::{Error, Result};
+
+/// Fragment header size
+pub const HEADER_SIZE: usize = 4;
+/// Messages being reassembled at the same time, the oldest one is dropped on overflow
+const MAX_PENDING: usize = 16;
+
+/// Splits messages into fragments.
+pub struct Fragmenter {
+    next_id: u16,
+    max_packet: usize,
+}
+
+impl Fragmenter {
+    /// Create fragmenter producing packets of at most `max_packet` bytes, header included
+    pub fn new(max_packet: usize) -> Self {
+        Self {
+            next_id: 0,
+            max_packet,
+        }
+    }
+
+    /// Split message into fragments ready to send.
+    ///
+    /// Fails with [`Error::MessageTooLarge`] if message needs more than 255 fragments.
+    pub fn split(&mut self, message: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let chunk = self
+            .max_packet
+            .checked_sub(HEADER_SIZE)
+            .filter(|chunk| *chunk > 0)
+            .ok_or(Error::InvalidArgument)?;
+        let count = std::cmp::max(message.len().div_ceil(chunk), 1);
+        let count = u8::try_from(count).map_err(|_| Error::MessageTooLarge)?;
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let fragments = (0..count)
+            .map(|index| {
+                let start = index as usize * chunk;
+                let end = std::cmp::min(start + chunk, message.len());
+                let mut fragment = Vec::with_capacity(HEADER_SIZE + end - start);
+                fragment.extend(id.to_be_bytes());
+                fragment.extend([index, count]);
+                fragment.extend(&message[start..end]);
+                fragment
+            })
+            .collect();
+        Ok(fragments)
+    }
+}
+
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Restores messages from fragments, tolerates reordering and loss.
+#[derive(Default)]
+pub struct Reassembler {
+    pending: HashMap<u16, Partial>,
+    order: VecDeque<u16>,
+}
+
+impl Reassembler {
+    /// Consume fragment, returns message once all its fragments are received.
+    ///
+    /// Malformed packets are ignored.
+    pub fn push(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        if packet.len() < HEADER_SIZE {
+            return None;
+        }
+        let id = u16::from_be_bytes([packet[0], packet[1]]);
+        let (index, count) = (packet[2] as usize, packet[3] as usize);
+        let payload = &packet[HEADER_SIZE..];
+        if index >= count {
+            return None;
+        }
+        if count == 1 {
+            return Some(payload.to_vec());
+        }
+
+        if !self.pending.contains_key(&id) {
+            if self.order.len() == MAX_PENDING {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.order.push_back(id);
+            let partial = Partial {
+                fragments: vec![None; count],
+                received: 0,
+            };
+            self.pending.insert(id, partial);
+        }
+
+        let partial = self.pending.get_mut(&id)?;
+        if partial.fragments.len() != count {
+            return None;
+        }
+        if partial.fragments[index].is_none() {
+            partial.fragments[index] = Some(payload.to_vec());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return None;
+        }
+
+        let partial = self.pending.remove(&id)?;
+        self.order.retain(|pending| *pending != id);
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let message = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut fragmenter = Fragmenter::new(104);
+        let mut reassembler = Reassembler::default();
+
+        let mut fragments = fragmenter.split(&message).unwrap();
+        assert_eq!(fragments.len(), 10);
+        assert!(fragments.iter().all(|f| f.len() <= 104));
+
+        // reordered and duplicated
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        fragments.insert(1, fragments[0].clone());
+        for fragment in &fragments {
+            assert_eq!(reassembler.push(fragment), None);
+        }
+        assert_eq!(reassembler.push(&last), Some(message));
+
+        let single = fragmenter.split(b"hi").unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(reassembler.push(&single[0]), Some(b"hi".to_vec()));
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            Fragmenter::new(HEADER_SIZE).split(b"x"),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            Fragmenter::new(HEADER_SIZE + 1).split(&[0; 256]),
+            Err(Error::MessageTooLarge)
+        );
+
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&[0, 0, 1]), None);
+        assert_eq!(reassembler.push(&[0, 0, 2, 2]), None);
+    }
+}
@@ -5,15 +5,92 @@
 //! [tests](https://github.com/VollmondT/juice-rs/blob/main/tests/connectivity.rs), also refer to
 //! the original library
 //! [tests](https://github.com/paullouisageneau/libjuice/blob/master/test/connectivity.c).
+//!
+//! [`Agent::negotiate`] runs the whole descriptions and candidates exchange over a
+//! [`signaling::Signaling`] transport.
+//!
+//! [`reliable::ReliableChannel`] adds ordered delivery with retransmissions on top of the agent
+//! data path, [`Agent::channel`] multiplexes independent streams over it.
+//!
+//! ## Features
+//! * `serde` - implement `Serialize`/`Deserialize` for public types like [`State`],
+//!   [`Candidate`], [`AgentConfig`], [`ReconnectPolicy`] and [`ServerCredentials`].
+//! * `webrtc` - `webrtc::JuiceConn` adapter implementing `webrtc_util::Conn` over [`Agent`].
+//! * `server` (default) - embedded STUN/TURN [`Server`].
+//! * `nettle` - build libjuice with Nettle hashing backend.
+//! * `localhost-address` - gather candidates on loopback addresses.
+//! * `local-address-translation` - translate local addresses to localhost when possible.
+//! * `system` - link installed libjuice found with pkg-config (vcpkg for MSVC) instead of
+//!   building the bundled one, `LIBJUICE_SYS_USE_PKG_CONFIG` environment variable does the same.
+//! * `fragment` - [`fragment`] module splitting messages larger than a single datagram.
+//! * `capi` - [`capi`] module with C functions for non-Rust applications.
+//! * `signaling-ws` - [`signaling::ws`] module with WebSocket signaling client and relay server.
+//! * `metrics` - publish agent metrics through the `metrics` facade, see
+//!   [`Builder::with_metrics`].
+//! * `psk` - [`secure`] module encrypting application data with a pre-shared key.
+//! * `nat-sim` - [`nat_sim`] module simulating NATs, loss and latency between two local agents.
+//! * `testing` - [`MockAgent`] implementing [`IceTransport`] for unit tests of downstream code.
+//! * `buildtime-bindgen` - generate libjuice bindings at build time instead of using
+//!   pregenerated ones, requires libclang.
+//!
+//! Features of the linked libjuice are reported by [`build_info`] and [`capabilities`], its version
+//! by [`version`].
 
-pub use agent::{handler::Handler, Agent, Builder, State};
-pub use error::{Error, Result};
+pub use agent::{
+    bandwidth::BandwidthEstimate,
+    candidate::{Candidate, CandidateType},
+    config::{AgentConfig, StunServerConfig, TurnServerConfig},
+    gathering::StalledServer,
+    handler::{Handler, RecvMeta},
+    negotiation::NegotiationState,
+    policy::{AddressPolicy, Cidr},
+    reconnect::ReconnectPolicy,
+    scheduling::{self, ThreadPriority},
+    sdp::DefaultCandidate,
+    stats::Stats,
+    subchannel::{self, SubChannel},
+    tagged::TaggedHandler,
+    Agent, Builder, ConcurrencyMode, PreparedAgent, State, WeakAgent,
+};
+pub use build_info::{
+    build_info, capabilities, version, BuildInfo, Capabilities, CryptoBackend, LibjuiceVersion,
+};
+pub use error::{DescriptionError, Error, Result};
+#[cfg(feature = "testing")]
+pub use mock::MockAgent;
+pub use pool::{AgentEvent, AgentPool, Builder as PoolBuilder, PoolEvent};
+#[cfg(feature = "server")]
 pub use server::{Builder as ServerBuilder, Credentials as ServerCredentials, Server};
+pub use transport::IceTransport;
 
 mod agent;
+mod build_info;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod clock;
 mod error;
+#[cfg(feature = "fragment")]
+pub mod fragment;
 mod log;
+mod marked;
+#[cfg(feature = "testing")]
+mod mock;
+#[cfg(feature = "nat-sim")]
+pub mod nat_sim;
+mod pool;
+pub mod reliable;
+#[cfg(feature = "psk")]
+pub mod secure;
+mod sequence;
+#[cfg(feature = "serde")]
+mod serde_util;
+#[cfg(feature = "server")]
 mod server;
+pub mod signaling;
+pub mod stun;
+mod transport;
+#[cfg(feature = "webrtc")]
+pub mod webrtc;
 
 #[cfg(test)]
 mod test_util;
@@ -0,0 +1,31 @@
+//! Routing of marked channel packets out of the regular recv path.
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::Handler;
+
+/// Input of a channel thread
+pub(crate) enum Event {
+    Packet(Vec<u8>),
+    Send(Vec<u8>),
+    Close,
+}
+
+/// Packets starting with `MARKER` received by the agent, consumed by the channel of the marker.
+pub struct Incoming<const MARKER: u8> {
+    pub(crate) tx: Sender<Event>,
+    pub(crate) rx: Receiver<Event>,
+}
+
+/// Wrap `handler` to route packets starting with `MARKER` to returned [`Incoming`]
+pub(crate) fn route<const MARKER: u8>(handler: Handler) -> (Handler, Incoming<MARKER>) {
+    let (tx, rx) = channel();
+    let packets = tx.clone();
+    let handler = handler.intercept_recv(move |packet| {
+        if packet.first() != Some(&MARKER) {
+            return false;
+        }
+        let _ = packets.send(Event::Packet(packet.to_vec()));
+        true
+    });
+    (handler, Incoming { tx, rx })
+}
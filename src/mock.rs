@@ -0,0 +1,329 @@
+//! Scriptable ICE agent for unit tests.
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use crate::agent::handler::{HandlerCell, RecvMeta};
+use crate::agent::MAX_DATAGRAM_SIZE;
+use crate::{Candidate, Error, Handler, IceTransport, NegotiationState, Result, State};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// In-memory [`IceTransport`] implementation.
+///
+/// Nothing happens on its own: state transitions, local candidates and incoming packets are
+/// driven by the test, handler closures are invoked synchronously from the calling thread.
+/// Agents created with [`MockAgent::pair`] deliver sent packets to each other.
+///
+/// # Example
+/// ```
+/// # use libjuice_rs::{Handler, IceTransport, MockAgent, State};
+/// let (first, second) = MockAgent::pair(
+///     Handler::default(),
+///     Handler::default().recv_handler(|packet| println!("received {:?}", packet)),
+/// );
+/// first.set_state(State::Connected);
+/// first.send(b"hello").unwrap();
+/// ```
+pub struct MockAgent {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    id: u64,
+    handler: HandlerCell,
+    state: Mutex<MockState>,
+}
+
+struct MockState {
+    state: State,
+    local_candidates: Vec<Candidate>,
+    remote_description: Option<String>,
+    remote_candidates: Vec<String>,
+    negotiation: NegotiationState,
+    peer: Weak<Inner>,
+    sent: Vec<Vec<u8>>,
+}
+
+impl MockAgent {
+    /// Create standalone agent, sent packets are kept for [`MockAgent::take_sent`]
+    pub fn new(handler: Handler) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                handler: HandlerCell::new(handler),
+                state: Mutex::new(MockState {
+                    state: State::Disconnected,
+                    local_candidates: vec![],
+                    remote_description: None,
+                    remote_candidates: vec![],
+                    negotiation: NegotiationState::default(),
+                    peer: Weak::new(),
+                    sent: vec![],
+                }),
+            }),
+        }
+    }
+
+    /// Create two agents delivering sent packets to each other
+    pub fn pair(first: Handler, second: Handler) -> (Self, Self) {
+        let first = Self::new(first);
+        let second = Self::new(second);
+        first.inner.state.lock().unwrap().peer = Arc::downgrade(&second.inner);
+        second.inner.state.lock().unwrap().peer = Arc::downgrade(&first.inner);
+        (first, second)
+    }
+
+    /// Switch state and notify the handler
+    pub fn set_state(&self, state: State) {
+        self.inner.state.lock().unwrap().state = state;
+        self.inner.handler.lock().on_state_changed(state);
+    }
+
+    /// Add local candidate and notify the handler as if it was just gathered
+    pub fn add_local_candidate(&self, sdp: &str) -> Result<()> {
+        let candidate = sdp.parse::<Candidate>()?;
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .local_candidates
+            .push(candidate.clone());
+
+        let mut h = self.inner.handler.lock();
+        h.on_candidate(candidate.to_string());
+        h.on_candidates(vec![candidate]);
+        Ok(())
+    }
+
+    /// Notify the handler that gathering is finished
+    pub fn finish_gathering(&self) {
+        self.inner.handler.lock().on_gathering_done();
+    }
+
+    /// Replace event handler, see [`crate::Agent::set_handler`]
+    pub fn set_handler(&self, handler: Handler) {
+        self.inner.handler.update(move |h| h.replace(handler))
+    }
+
+    /// Drop incoming packet handlers
+    pub fn clear_recv_handler(&self) {
+        self.inner.handler.update(Handler::clear_recv)
+    }
+
+    /// Deliver incoming packet to the handler
+    pub fn inject_packet(&self, packet: &[u8]) {
+        self.inner.deliver(packet);
+    }
+
+    /// Get remote description set by the code under test
+    pub fn remote_description(&self) -> Option<String> {
+        self.inner.state.lock().unwrap().remote_description.clone()
+    }
+
+    /// Get remote candidates added by the code under test
+    pub fn remote_candidates(&self) -> Vec<String> {
+        self.inner.state.lock().unwrap().remote_candidates.clone()
+    }
+
+    /// Whether remote gathering was signaled done
+    pub fn is_remote_gathering_done(&self) -> bool {
+        self.inner.state.lock().unwrap().negotiation == NegotiationState::GatheringDone
+    }
+
+    /// Take packets sent by standalone agent or after the peer is dropped
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.inner.state.lock().unwrap().sent)
+    }
+}
+
+impl Inner {
+    fn deliver(&self, packet: &[u8]) {
+        let timestamp = Instant::now();
+        let mut h = self.handler.lock();
+        h.on_recv(packet, || RecvMeta {
+            timestamp,
+            via_relay: false,
+            remote: (Ipv4Addr::UNSPECIFIED, 0).into(),
+        });
+    }
+}
+
+/// Whether packets can be sent in given state
+fn is_connected(state: State) -> bool {
+    matches!(state, State::Connected | State::Completed)
+}
+
+impl IceTransport for MockAgent {
+    fn state(&self) -> State {
+        self.inner.state.lock().unwrap().state
+    }
+
+    fn local_description(&self) -> Result<String> {
+        let state = self.inner.state.lock().unwrap();
+        let mut sdp = format!(
+            "a=ice-ufrag:mock{}\r\na=ice-pwd:mockpassword{:010}\r\n",
+            self.inner.id, self.inner.id
+        );
+        for candidate in &state.local_candidates {
+            sdp.push_str(candidate.as_sdp());
+            sdp.push_str("\r\n");
+        }
+        Ok(sdp)
+    }
+
+    fn gather_candidates(&self) -> Result<()> {
+        if self.state() != State::Disconnected {
+            return Err(Error::Failed);
+        }
+        self.set_state(State::Gathering);
+        Ok(())
+    }
+
+    fn set_remote_description(&self, sdp: String) -> Result<()> {
+        let mut state = self.inner.state.lock().unwrap();
+        state
+            .negotiation
+            .expect(NegotiationState::AwaitingDescription)?;
+        state.remote_description = Some(sdp);
+        state.negotiation = NegotiationState::DescriptionSet;
+        Ok(())
+    }
+
+    fn add_remote_candidate(&self, sdp: String) -> Result<()> {
+        let mut state = self.inner.state.lock().unwrap();
+        state.negotiation.expect(NegotiationState::DescriptionSet)?;
+        sdp.parse::<Candidate>()?;
+        state.remote_candidates.push(sdp);
+        Ok(())
+    }
+
+    fn set_remote_gathering_done(&self) -> Result<()> {
+        let mut state = self.inner.state.lock().unwrap();
+        state.negotiation.expect(NegotiationState::DescriptionSet)?;
+        state.negotiation = NegotiationState::GatheringDone;
+        Ok(())
+    }
+
+    fn send(&self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::MessageTooLarge);
+        }
+        let peer = {
+            let mut state = self.inner.state.lock().unwrap();
+            if !is_connected(state.state) {
+                return Err(Error::Failed);
+            }
+            match state.peer.upgrade() {
+                Some(peer) => peer,
+                None => {
+                    state.sent.push(data.to_vec());
+                    return Ok(());
+                }
+            }
+        };
+        peer.deliver(data);
+        Ok(())
+    }
+
+    /// First local and first remote candidates once connected
+    fn selected_candidates(&self) -> Result<(String, String)> {
+        let state = self.inner.state.lock().unwrap();
+        match (
+            state.local_candidates.first(),
+            state.remote_candidates.first(),
+        ) {
+            (Some(local), Some(remote)) if is_connected(state.state) => {
+                Ok((local.to_string(), remote.clone()))
+            }
+            _ => Err(Error::NotAvailable),
+        }
+    }
+
+    fn selected_addresses(&self) -> Result<(String, String)> {
+        let (local, remote) = self.selected_candidates()?;
+        let addr = |sdp: String| -> Result<SocketAddr> {
+            sdp.parse::<Candidate>()?.addr().ok_or(Error::NotAvailable)
+        };
+        Ok((addr(local)?.to_string(), addr(remote)?.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    const CANDIDATE: &str = "a=candidate:1 1 UDP 2122317823 192.168.1.5 54321 typ host";
+
+    #[test]
+    fn scripted() {
+        let (tx, rx) = channel();
+        let handler = Handler::default()
+            .state_handler({
+                let tx = tx.clone();
+                move |state| tx.send(format!("{:?}", state)).unwrap()
+            })
+            .candidate_handler({
+                let tx = tx.clone();
+                move |sdp| tx.send(sdp).unwrap()
+            })
+            .gathering_done_handler(move || tx.send("done".to_string()).unwrap());
+        let agent = MockAgent::new(handler);
+
+        agent.gather_candidates().unwrap();
+        agent.add_local_candidate(CANDIDATE).unwrap();
+        assert!(agent.add_local_candidate("a=ice-ufrag:abcd").is_err());
+        agent.finish_gathering();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            ["Gathering", CANDIDATE, "done"]
+        );
+        assert!(agent.local_description().unwrap().contains(CANDIDATE));
+
+        assert_eq!(
+            agent.add_remote_candidate(CANDIDATE.to_string()),
+            Err(Error::InvalidState(
+                NegotiationState::DescriptionSet,
+                NegotiationState::AwaitingDescription
+            ))
+        );
+        agent
+            .set_remote_description("a=ice-ufrag:abcd".to_string())
+            .unwrap();
+        agent.add_remote_candidate(CANDIDATE.to_string()).unwrap();
+        agent.set_remote_gathering_done().unwrap();
+        assert!(agent.is_remote_gathering_done());
+        assert!(agent.set_remote_gathering_done().is_err());
+        assert_eq!(agent.send(b"early"), Err(Error::Failed));
+        agent.set_state(State::Connected);
+        assert_eq!(
+            agent.selected_addresses().unwrap(),
+            (
+                "192.168.1.5:54321".to_string(),
+                "192.168.1.5:54321".to_string()
+            )
+        );
+
+        agent.send(b"hello").unwrap();
+        assert_eq!(agent.take_sent(), [b"hello".to_vec()]);
+        assert_eq!(agent.send(&[0; 5000]), Err(Error::MessageTooLarge));
+    }
+
+    #[test]
+    fn loopback() {
+        let (tx, rx) = channel();
+        let (first, second) = MockAgent::pair(
+            Handler::default(),
+            Handler::default().recv_handler(move |packet| tx.send(packet.to_vec()).unwrap()),
+        );
+        first.set_state(State::Connected);
+        first.send(b"hello").unwrap();
+        assert_eq!(rx.try_recv().unwrap(), b"hello");
+
+        drop(second);
+        first.send(b"lost").unwrap();
+        assert_eq!(first.take_sent(), [b"lost".to_vec()]);
+    }
+}
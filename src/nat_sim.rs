@@ -0,0 +1,576 @@
+//! Simulated NATs between two agents on loopback, for connectivity and TURN fallback tests.
+//!
+//! Agents are bound to 127.0.0.1 and never learn each other's addresses: [`connect`] replaces
+//! local candidates with public endpoints of simulated NATs, a user-space UDP proxy owning the
+//! endpoints forwards packets applying NAT mapping and filtering, loss and latency. TURN servers
+//! are reached directly, relayed candidates are passed unchanged.
+//!
+//! # Example
+//! ```no_run
+//! # use std::time::Duration;
+//! # use libjuice_rs::{Agent, Handler, State};
+//! # use libjuice_rs::nat_sim::{self, FullConeNat, SymmetricNat};
+//! let first = SymmetricNat::wrap(Agent::builder(Handler::default()))
+//!     .build()
+//!     .unwrap();
+//! let second = FullConeNat::wrap(Agent::builder(Handler::default()))
+//!     .with_loss(0.1)
+//!     .with_latency(Duration::from_millis(50))
+//!     .build()
+//!     .unwrap();
+//! let _sim = nat_sim::connect(&first, &second, Duration::from_secs(5)).unwrap();
+//! // wait for State::Connected, the simulation runs until dropped
+//! ```
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Agent, Builder as AgentBuilder, Candidate, CandidateType, Error, Result};
+
+/// Proxy sockets polling period
+const POLL: Duration = Duration::from_millis(20);
+/// Destination of the mapping advertised to the remote agent, as seen by a STUN server
+const STUN_SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 3478);
+
+/// NAT behaviour.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NatType {
+    /// Endpoint-independent mapping and filtering, single public endpoint reachable by anyone
+    FullCone,
+    /// Endpoint-dependent mapping and filtering, public endpoint per destination accepting
+    /// packets from that destination only. Two symmetric NATs need TURN to connect.
+    Symmetric,
+}
+
+/// Full cone NAT.
+pub struct FullConeNat;
+
+impl FullConeNat {
+    /// Put agent built by `builder` behind full cone NAT
+    pub fn wrap(builder: AgentBuilder) -> Builder {
+        Builder::new(builder, NatType::FullCone)
+    }
+}
+
+/// Symmetric NAT.
+pub struct SymmetricNat;
+
+impl SymmetricNat {
+    /// Put agent built by `builder` behind symmetric NAT
+    pub fn wrap(builder: AgentBuilder) -> Builder {
+        Builder::new(builder, NatType::Symmetric)
+    }
+}
+
+/// Outgoing path conditions of an agent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Nat {
+    kind: NatType,
+    loss: f64,
+    latency: Duration,
+}
+
+/// Builder of an agent behind simulated NAT.
+pub struct Builder {
+    builder: AgentBuilder,
+    nat: Nat,
+}
+
+impl Builder {
+    fn new(builder: AgentBuilder, kind: NatType) -> Self {
+        Self {
+            builder,
+            nat: Nat {
+                kind,
+                loss: 0.0,
+                latency: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Drop given ratio (0.0 to 1.0) of packets sent by the agent
+    pub fn with_loss(mut self, ratio: f64) -> Self {
+        self.nat.loss = ratio;
+        self
+    }
+
+    /// Delay packets sent by the agent
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.nat.latency = latency;
+        self
+    }
+
+    /// Build agent bound to 127.0.0.1, without STUN server
+    pub fn build(self) -> Result<NatAgent> {
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        let agent = self
+            .builder
+            .without_stun()
+            .with_bind_address(&IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .chain_gathering_done(move || {
+                let _ = tx.lock().unwrap().send(());
+            })
+            .build()?;
+        Ok(NatAgent {
+            agent,
+            nat: self.nat,
+            gathered: Mutex::new(rx),
+        })
+    }
+}
+
+/// Agent behind simulated NAT, see [`connect`].
+pub struct NatAgent {
+    agent: Agent,
+    nat: Nat,
+    gathered: Mutex<Receiver<()>>,
+}
+
+impl NatAgent {
+    /// Get the agent
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// Get NAT type
+    pub fn nat_type(&self) -> NatType {
+        self.nat.kind
+    }
+
+    fn gather(&self, deadline: Instant) -> Result<String> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.gathered.lock().unwrap().recv_timeout(timeout) {
+            Ok(()) => self.agent.get_local_description(),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Failed),
+        }
+    }
+}
+
+/// Gather candidates and exchange descriptions translated by NATs, which run until the returned
+/// simulation is dropped.
+///
+/// Returns once descriptions are set, connectivity is reported by the agents state.
+pub fn connect(first: &NatAgent, second: &NatAgent, timeout: Duration) -> Result<Simulation> {
+    let deadline = Instant::now() + timeout;
+    first.agent.gather_candidates()?;
+    second.agent.gather_candidates()?;
+    let descriptions = [first.gather(deadline)?, second.gather(deadline)?];
+
+    let real = |sdp: &str| host_address(sdp).ok_or(Error::NotAvailable);
+    let sim = Simulation::start([
+        (real(&descriptions[0])?, first.nat),
+        (real(&descriptions[1])?, second.nat),
+    ])?;
+    first
+        .agent
+        .set_remote_description(translate(&descriptions[1], sim.public_address(1)))?;
+    second
+        .agent
+        .set_remote_description(translate(&descriptions[0], sim.public_address(0)))?;
+    Ok(sim)
+}
+
+/// Get address of the first host candidate
+fn host_address(sdp: &str) -> Option<SocketAddr> {
+    sdp.lines()
+        .filter_map(|line| line.parse::<Candidate>().ok())
+        .find(|c| c.kind() == CandidateType::Host)
+        .and_then(|c| c.addr())
+}
+
+/// Replace the first host candidate with public endpoint, drop other candidates except relayed
+fn translate(sdp: &str, public: SocketAddr) -> String {
+    let mut replaced = false;
+    let lines = sdp
+        .lines()
+        .filter_map(|line| match line.parse::<Candidate>() {
+            Err(_) => Some(line.to_string()),
+            Ok(c) if c.kind() == CandidateType::Relayed => Some(line.to_string()),
+            Ok(c) if c.kind() == CandidateType::Host && !replaced => {
+                replaced = true;
+                Some(format!(
+                    "a=candidate:{} {} {} {} {} {} typ host",
+                    c.foundation(),
+                    c.component(),
+                    c.transport(),
+                    c.priority(),
+                    public.ip(),
+                    public.port()
+                ))
+            }
+            Ok(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut out = lines.join("\r\n");
+    if sdp.ends_with('\n') {
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Agent side of the simulation.
+struct Side {
+    real: SocketAddr,
+    nat: Nat,
+    /// xorshift state
+    rng: Mutex<u32>,
+}
+
+impl Side {
+    fn new(real: SocketAddr, nat: Nat) -> Self {
+        let seed = RandomState::new().build_hasher().finish() as u32;
+        Self {
+            real,
+            nat,
+            rng: Mutex::new(seed | 1),
+        }
+    }
+
+    /// Whether the next packet sent is lost
+    fn lose(&self) -> bool {
+        if self.nat.loss <= 0.0 {
+            return false;
+        }
+        let mut x = self.rng.lock().unwrap();
+        *x ^= *x << 13;
+        *x ^= *x >> 17;
+        *x ^= *x << 5;
+        (*x as f64 / u32::MAX as f64) < self.nat.loss
+    }
+}
+
+/// Public endpoint of a NAT.
+struct Endpoint {
+    socket: Arc<UdpSocket>,
+    side: usize,
+    /// Destination the endpoint was mapped for, unset if endpoint-independent
+    dest: Option<SocketAddr>,
+}
+
+#[derive(Default)]
+struct Table {
+    endpoints: HashMap<SocketAddr, Endpoint>,
+    /// Public endpoints by side and destination
+    mappings: HashMap<(usize, Option<SocketAddr>), SocketAddr>,
+}
+
+/// Packet waiting for latency to pass.
+struct Delayed {
+    at: Instant,
+    seq: u64,
+    socket: Arc<UdpSocket>,
+    packet: Vec<u8>,
+    to: SocketAddr,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    // earliest first in max-heap
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+struct Shared {
+    sides: [Side; 2],
+    table: Mutex<Table>,
+    delayed: Mutex<(u64, Sender<Delayed>)>,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    /// Get public endpoint of the side for given destination, created on first use
+    fn mapping(self: &Arc<Self>, side: usize, dest: SocketAddr) -> Result<SocketAddr> {
+        let key = match self.sides[side].nat.kind {
+            NatType::FullCone => None,
+            NatType::Symmetric => Some(dest),
+        };
+        let mut table = self.table.lock().unwrap();
+        if let Some(addr) = table.mappings.get(&(side, key)) {
+            return Ok(*addr);
+        }
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).map_err(failed)?;
+        socket.set_read_timeout(Some(POLL)).map_err(failed)?;
+        let addr = socket.local_addr().map_err(failed)?;
+        let socket = Arc::new(socket);
+        table.mappings.insert((side, key), addr);
+        table.endpoints.insert(
+            addr,
+            Endpoint {
+                socket: socket.clone(),
+                side,
+                dest: key,
+            },
+        );
+        log::debug!("nat {} mapped {} for {}", side, addr, dest);
+        thread::spawn({
+            let shared = self.clone();
+            move || shared.serve(socket, addr)
+        });
+        Ok(addr)
+    }
+
+    fn serve(self: Arc<Self>, socket: Arc<UdpSocket>, addr: SocketAddr) {
+        let mut buf = [0u8; 65536];
+        while !self.stop.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    if let Err(e) = self.forward(addr, &buf[..len], from) {
+                        log::warn!("nat failed to forward packet to {}: {}", addr, e);
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    log::warn!("nat endpoint {} failed: {}", addr, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Pass packet received on public endpoint `to` to the agent behind it
+    fn forward(self: &Arc<Self>, to: SocketAddr, packet: &[u8], from: SocketAddr) -> Result<()> {
+        let sender = match self.sides.iter().position(|side| side.real == from) {
+            Some(sender) => sender,
+            None => {
+                log::debug!("nat dropping packet from {}", from);
+                return Ok(());
+            }
+        };
+        let (owner, dest) = {
+            let table = self.table.lock().unwrap();
+            let endpoint = &table.endpoints[&to];
+            (endpoint.side, endpoint.dest)
+        };
+        if owner == sender {
+            log::debug!("nat dropping hairpin packet to {}", to);
+            return Ok(());
+        }
+        let mapped = self.mapping(sender, to)?;
+        if dest.is_some() && dest != Some(mapped) {
+            log::debug!("nat {} filtered packet from {}", owner, mapped);
+            return Ok(());
+        }
+        if self.sides[sender].lose() {
+            return Ok(());
+        }
+
+        let socket = self.table.lock().unwrap().endpoints[&mapped].socket.clone();
+        let to = self.sides[owner].real;
+        let latency = self.sides[sender].nat.latency;
+        if latency.is_zero() {
+            socket.send_to(packet, to).map_err(failed)?;
+            return Ok(());
+        }
+        let mut delayed = self.delayed.lock().unwrap();
+        delayed.0 += 1;
+        let _ = delayed.1.send(Delayed {
+            at: Instant::now() + latency,
+            seq: delayed.0,
+            socket,
+            packet: packet.to_vec(),
+            to,
+        });
+        Ok(())
+    }
+}
+
+fn failed(e: std::io::Error) -> Error {
+    log::warn!("nat simulation failed: {}", e);
+    Error::Failed
+}
+
+/// Send delayed packets when due, until all senders are gone
+fn deliver(rx: Receiver<Delayed>) {
+    let mut queue = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while queue.peek().is_some_and(|p: &Delayed| p.at <= now) {
+            let p = queue.pop().unwrap();
+            if let Err(e) = p.socket.send_to(&p.packet, p.to) {
+                log::warn!("nat failed to send delayed packet to {}: {}", p.to, e);
+            }
+        }
+        let timeout = queue
+            .peek()
+            .map_or(POLL, |p| p.at.saturating_duration_since(now));
+        match rx.recv_timeout(timeout) {
+            Ok(p) => queue.push(p),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) if queue.is_empty() => break,
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(timeout),
+        }
+    }
+}
+
+/// Running NAT simulation, stopped on drop.
+pub struct Simulation {
+    shared: Arc<Shared>,
+    public: [SocketAddr; 2],
+}
+
+impl Simulation {
+    fn start(sides: [(SocketAddr, Nat); 2]) -> Result<Self> {
+        let (tx, rx) = channel();
+        thread::spawn(move || deliver(rx));
+        let shared = Arc::new(Shared {
+            sides: sides.map(|(real, nat)| Side::new(real, nat)),
+            table: Mutex::new(Table::default()),
+            delayed: Mutex::new((0, tx)),
+            stop: AtomicBool::new(false),
+        });
+        let public = [
+            shared.mapping(0, STUN_SERVER)?,
+            shared.mapping(1, STUN_SERVER)?,
+        ];
+        Ok(Self { shared, public })
+    }
+
+    /// Get public endpoint of the first (0) or second (1) agent advertised to the other one
+    pub fn public_address(&self, index: usize) -> SocketAddr {
+        self.public[index]
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nat(kind: NatType) -> Nat {
+        Nat {
+            kind,
+            loss: 0.0,
+            latency: Duration::ZERO,
+        }
+    }
+
+    fn socket() -> UdpSocket {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        socket
+    }
+
+    fn recv(socket: &UdpSocket) -> Option<(Vec<u8>, SocketAddr)> {
+        let mut buf = [0u8; 16];
+        let (len, from) = socket.recv_from(&mut buf).ok()?;
+        Some((buf[..len].to_vec(), from))
+    }
+
+    #[test]
+    fn full_cone_to_symmetric() {
+        let (cone, symmetric) = (socket(), socket());
+        let sim = Simulation::start([
+            (cone.local_addr().unwrap(), nat(NatType::FullCone)),
+            (symmetric.local_addr().unwrap(), nat(NatType::Symmetric)),
+        ])
+        .unwrap();
+
+        // unsolicited packet to the symmetric side is filtered
+        cone.send_to(b"a", sim.public_address(1)).unwrap();
+        assert_eq!(recv(&symmetric), None);
+
+        symmetric.send_to(b"b", sim.public_address(0)).unwrap();
+        let (packet, mapped) = recv(&cone).unwrap();
+        assert_eq!(packet, b"b");
+        assert_ne!(mapped, sim.public_address(1));
+
+        // reply through the mapping created by the symmetric side
+        cone.send_to(b"c", mapped).unwrap();
+        assert_eq!(
+            recv(&symmetric),
+            Some((b"c".to_vec(), sim.public_address(0)))
+        );
+    }
+
+    #[test]
+    fn symmetric_pair() {
+        let (first, second) = (socket(), socket());
+        let sim = Simulation::start([
+            (first.local_addr().unwrap(), nat(NatType::Symmetric)),
+            (second.local_addr().unwrap(), nat(NatType::Symmetric)),
+        ])
+        .unwrap();
+
+        first.send_to(b"a", sim.public_address(1)).unwrap();
+        second.send_to(b"b", sim.public_address(0)).unwrap();
+        assert_eq!(recv(&first), None);
+        assert_eq!(recv(&second), None);
+    }
+
+    #[test]
+    fn loss_and_latency() {
+        let (first, second) = (socket(), socket());
+        let latency = Duration::from_millis(50);
+        let sim = Simulation::start([
+            (
+                first.local_addr().unwrap(),
+                Nat {
+                    latency,
+                    ..nat(NatType::FullCone)
+                },
+            ),
+            (
+                second.local_addr().unwrap(),
+                Nat {
+                    loss: 1.0,
+                    ..nat(NatType::FullCone)
+                },
+            ),
+        ])
+        .unwrap();
+
+        let sent = Instant::now();
+        first.send_to(b"a", sim.public_address(1)).unwrap();
+        assert_eq!(recv(&second).unwrap().0, b"a");
+        assert!(sent.elapsed() >= latency);
+
+        second.send_to(b"b", sim.public_address(0)).unwrap();
+        assert_eq!(recv(&first), None);
+    }
+
+    #[test]
+    fn translate_description() {
+        let sdp = "a=ice-ufrag:abcd\r\n\
+                   a=candidate:1 1 UDP 2122317823 127.0.0.1 50000 typ host\r\n\
+                   a=candidate:2 1 UDP 1686052607 127.0.0.1 50000 typ srflx raddr 0.0.0.0 rport 0\r\n\
+                   a=candidate:3 1 UDP 16777215 127.0.0.1 60000 typ relay raddr 0.0.0.0 rport 0\r\n";
+        assert_eq!(host_address(sdp), Some("127.0.0.1:50000".parse().unwrap()));
+        assert_eq!(
+            translate(sdp, "127.0.0.1:40000".parse().unwrap()),
+            "a=ice-ufrag:abcd\r\n\
+             a=candidate:1 1 UDP 2122317823 127.0.0.1 40000 typ host\r\n\
+             a=candidate:3 1 UDP 16777215 127.0.0.1 60000 typ relay raddr 0.0.0.0 rport 0\r\n"
+        );
+    }
+}
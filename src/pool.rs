@@ -0,0 +1,335 @@
+//! Agents pool.
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::sequence;
+use crate::{
+    Agent, Builder as AgentBuilder, ConcurrencyMode, Error, Handler, Result, State, Stats,
+};
+
+/// Period to check whether the pool is still alive
+const LIVENESS_PERIOD: Duration = Duration::from_secs(1);
+
+/// Agent event delivered through the pool event stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    StateChanged(State),
+    Candidate(String),
+    GatheringDone,
+    /// Received packet, only with [`Builder::with_recv_events`]
+    Recv(Vec<u8>),
+}
+
+/// Event of a pool agent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEvent<K> {
+    /// Agent id
+    pub id: K,
+    /// Crate-wide sequence number, totally orders events of all agents
+    pub seq: u64,
+    pub event: AgentEvent,
+}
+
+impl<K> PoolEvent<K> {
+    fn new(id: K, event: AgentEvent) -> Self {
+        Self {
+            id,
+            seq: sequence::next(),
+            event,
+        }
+    }
+}
+
+type Agents<K> = Mutex<HashMap<K, Agent>>;
+
+enum Command<K> {
+    Gather(K),
+    Done(K),
+}
+
+/// Agents pool builder.
+pub struct Builder {
+    concurrency_mode: ConcurrencyMode,
+    gathering_limit: usize,
+    gathering_interval: Duration,
+    recv_events: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            concurrency_mode: ConcurrencyMode::Poll,
+            gathering_limit: 16,
+            gathering_interval: Duration::from_millis(20),
+            recv_events: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Set concurrency mode of the pool agents (default is [`ConcurrencyMode::Poll`])
+    pub fn with_concurrency_mode(mut self, mode: ConcurrencyMode) -> Self {
+        self.concurrency_mode = mode;
+        self
+    }
+
+    /// Set maximal number of agents gathering candidates at the same time (default is 16)
+    pub fn with_gathering_limit(mut self, limit: usize) -> Self {
+        self.gathering_limit = std::cmp::max(limit, 1);
+        self
+    }
+
+    /// Set minimal interval between gathering starts (default is 20ms)
+    pub fn with_gathering_interval(mut self, interval: Duration) -> Self {
+        self.gathering_interval = interval;
+        self
+    }
+
+    /// Forward received packets to the event stream as [`AgentEvent::Recv`].
+    ///
+    /// Every packet is copied into the stream, which is unbounded, so the consumer has to keep up
+    /// with the data rate.
+    pub fn with_recv_events(mut self) -> Self {
+        self.recv_events = true;
+        self
+    }
+
+    /// Build [`AgentPool`] along with the stream of events of all its agents.
+    pub fn build<K>(self) -> (AgentPool<K>, Receiver<PoolEvent<K>>)
+    where
+        K: Clone + Eq + Hash + Send + Sync + 'static,
+    {
+        let agents = Arc::new(Mutex::new(HashMap::new()));
+        let (events_tx, events_rx) = channel();
+        let (commands_tx, commands_rx) = channel();
+
+        {
+            let agents = Arc::downgrade(&agents);
+            let limit = self.gathering_limit;
+            let interval = self.gathering_interval;
+            thread::spawn(move || schedule(agents, limit, interval, commands_rx));
+        }
+
+        let pool = AgentPool {
+            concurrency_mode: self.concurrency_mode,
+            recv_events: self.recv_events,
+            agents,
+            events: Mutex::new(events_tx),
+            commands: Mutex::new(commands_tx),
+        };
+        (pool, events_rx)
+    }
+}
+
+/// Set of agents sharing gathering budget and a single event stream.
+///
+/// Events carry crate-wide sequence number, so events of different agents can be ordered even
+/// if they are received out of order.
+///
+/// # Example
+/// ```no_run
+/// # use libjuice_rs::{AgentPool, ConcurrencyMode};
+/// let (pool, events) = AgentPool::builder()
+///     .with_concurrency_mode(ConcurrencyMode::Mux)
+///     .with_gathering_limit(4)
+///     .build();
+///
+/// for id in 0..100u32 {
+///     pool.add_agent(id, |builder| builder).unwrap();
+///     pool.gather(&id).unwrap();
+/// }
+///
+/// while let Ok(e) = events.recv() {
+///     println!("#{} agent {}: {:?}", e.seq, e.id, e.event);
+/// }
+/// ```
+pub struct AgentPool<K> {
+    concurrency_mode: ConcurrencyMode,
+    recv_events: bool,
+    agents: Arc<Agents<K>>,
+    events: Mutex<Sender<PoolEvent<K>>>,
+    commands: Mutex<Sender<Command<K>>>,
+}
+
+impl AgentPool<()> {
+    /// Create pool builder
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<K> AgentPool<K>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+{
+    /// Create agent with given id.
+    ///
+    /// `configure` may adjust the agent builder, concurrency mode and handler are set by the pool.
+    /// The pool is locked meanwhile, so `configure` must not call the pool.
+    pub fn add_agent<F>(&self, id: K, configure: F) -> Result<Agent>
+    where
+        F: FnOnce(AgentBuilder) -> AgentBuilder,
+    {
+        let mut agents = self.agents.lock().unwrap();
+        let entry = match agents.entry(id) {
+            Entry::Occupied(_) => return Err(Error::InvalidArgument),
+            Entry::Vacant(entry) => entry,
+        };
+
+        let handler = self.handler(entry.key().clone());
+        let builder = Agent::builder(handler);
+        let agent = configure(builder)
+            .with_concurrency_mode(self.concurrency_mode)
+            .build()?;
+
+        Ok(entry.insert(agent).clone())
+    }
+
+    /// Get agent by id
+    pub fn get(&self, id: &K) -> Option<Agent> {
+        self.agents.lock().unwrap().get(id).cloned()
+    }
+
+    /// Remove agent from the pool
+    pub fn remove(&self, id: &K) -> Option<Agent> {
+        let agent = self.agents.lock().unwrap().remove(id);
+        if agent.is_some() {
+            self.command(Command::Done(id.clone()));
+        }
+        agent
+    }
+
+    /// Schedule candidates gathering, respecting the pool gathering limit
+    pub fn gather(&self, id: &K) -> Result<()> {
+        if !self.agents.lock().unwrap().contains_key(id) {
+            return Err(Error::NotAvailable);
+        }
+        self.command(Command::Gather(id.clone()));
+        Ok(())
+    }
+
+    /// Number of agents in the pool
+    pub fn len(&self) -> usize {
+        self.agents.lock().unwrap().len()
+    }
+
+    /// Whether pool has no agents
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get aggregated data path statistics of all agents in the pool
+    pub fn stats(&self) -> Stats {
+        let agents = self.agents.lock().unwrap();
+        agents.values().fold(Stats::default(), |mut acc, agent| {
+            acc += agent.stats();
+            acc
+        })
+    }
+
+    fn command(&self, cmd: Command<K>) {
+        let _ = self.commands.lock().unwrap().send(cmd);
+    }
+
+    /// Create handler forwarding events to the pool stream
+    fn handler(&self, id: K) -> Handler {
+        let events = self.events.lock().unwrap().clone();
+        let commands = self.commands.lock().unwrap().clone();
+
+        let state_events = Mutex::new(events.clone());
+        let state_commands = Mutex::new(commands.clone());
+        let candidate_events = events.clone();
+        let done_events = events.clone();
+        let recv_events = events;
+        let (state_id, candidate_id, done_id, recv_id) = (id.clone(), id.clone(), id.clone(), id);
+
+        let handler = Handler::default()
+            .state_handler(move |state| {
+                if state == State::Failed {
+                    // failed agent doesn't occupy gathering slot anymore
+                    let cmd = Command::Done(state_id.clone());
+                    let _ = state_commands.lock().unwrap().send(cmd);
+                }
+                let event = PoolEvent::new(state_id.clone(), AgentEvent::StateChanged(state));
+                let _ = state_events.lock().unwrap().send(event);
+            })
+            .candidate_handler(move |sdp| {
+                let event = PoolEvent::new(candidate_id.clone(), AgentEvent::Candidate(sdp));
+                let _ = candidate_events.send(event);
+            })
+            .gathering_done_handler(move || {
+                let _ = commands.send(Command::Done(done_id.clone()));
+                let _ =
+                    done_events.send(PoolEvent::new(done_id.clone(), AgentEvent::GatheringDone));
+            });
+        if !self.recv_events {
+            return handler;
+        }
+        handler.recv_handler(move |packet| {
+            let event = PoolEvent::new(recv_id.clone(), AgentEvent::Recv(packet.to_vec()));
+            let _ = recv_events.send(event);
+        })
+    }
+}
+
+/// Gathering scheduler loop, lives until the pool is dropped
+fn schedule<K>(
+    agents: Weak<Agents<K>>,
+    limit: usize,
+    interval: Duration,
+    commands: Receiver<Command<K>>,
+) where
+    K: Clone + Eq + Hash,
+{
+    let mut queue = VecDeque::new();
+    let mut active = HashSet::new();
+    let mut last_start: Option<Instant> = None;
+
+    loop {
+        let mut wait = LIVENESS_PERIOD;
+
+        while active.len() < limit && !queue.is_empty() {
+            let now = Instant::now();
+            if let Some(next) = last_start.map(|t| t + interval) {
+                if next > now {
+                    wait = next - now;
+                    break;
+                }
+            }
+
+            let id = queue.pop_front().unwrap();
+            let agent = match agents.upgrade() {
+                Some(agents) => agents.lock().unwrap().get(&id).cloned(),
+                None => return,
+            };
+            if let Some(agent) = agent {
+                match agent.gather_candidates() {
+                    Ok(_) => {
+                        active.insert(id);
+                        last_start = Some(now);
+                    }
+                    Err(e) => log::error!("failed to start gathering: {}", e),
+                }
+            }
+        }
+
+        match commands.recv_timeout(wait) {
+            Ok(Command::Gather(id)) => queue.push_back(id),
+            Ok(Command::Done(id)) => {
+                active.remove(&id);
+                queue.retain(|queued| queued != &id);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if agents.strong_count() == 0 {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Reliable ordered messages over the ICE data path.
+//!
+//! Lightweight ARQ for control messages: sequence numbers, cumulative acknowledgements,
+//! retransmission on timeout and AIMD congestion window. Channel packets are marked with a
+//! leading byte and are filtered out of the regular recv handler, so the channel can share the
+//! agent with other traffic as long as it doesn't start with the same byte.
+//!
+//! # Example
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use libjuice_rs::{Agent, Handler};
+//! # use libjuice_rs::reliable::{channel_handler, ReliableChannel};
+//! let (handler, incoming) = channel_handler(Handler::default());
+//! let agent = Arc::new(Agent::builder(handler).build().unwrap());
+//! let channel = ReliableChannel::new(agent.clone(), incoming, |message| {
+//!     println!("received {:?}", message);
+//! });
+//! // ... exchange descriptions and wait for connection
+//! channel.send(b"hello").unwrap();
+//! ```
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::agent::MAX_DATAGRAM_SIZE;
+use crate::marked::{self, Event};
+use crate::{Error, Handler, IceTransport, Result};
+
+/// First byte of channel packets
+pub const MARKER: u8 = 0xFA;
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+const HEADER_SIZE: usize = 6;
+/// Largest message carried by a single packet
+pub const MAX_MESSAGE_SIZE: usize = MAX_DATAGRAM_SIZE - HEADER_SIZE;
+
+const INITIAL_RTO: Duration = Duration::from_millis(200);
+const MIN_RTO: Duration = Duration::from_millis(50);
+const MAX_RTO: Duration = Duration::from_secs(3);
+const INITIAL_WINDOW: f64 = 4.0;
+const MAX_WINDOW: f64 = 256.0;
+/// Retransmissions of a packet before the peer is considered gone, about 15s with backoff
+const MAX_RETRANSMITS: u32 = 8;
+/// Out of order packets buffered by receiver
+const RECV_WINDOW: u32 = 256;
+
+/// Channel packets received by the agent, consumed by [`ReliableChannel`].
+pub type Incoming = marked::Incoming<MARKER>;
+
+/// Route channel packets received by the agent built with returned handler to
+/// [`ReliableChannel`].
+///
+/// Other packets are passed to the recv handlers of the given handler.
+pub fn channel_handler(handler: Handler) -> (Handler, Incoming) {
+    marked::route(handler)
+}
+
+/// Reliable ordered message channel over ICE transport.
+///
+/// Messages are handled on a dedicated thread, which lives until the channel is dropped or the
+/// peer stops acknowledging. A packet unacknowledged after several retransmissions fails the
+/// channel, [`ReliableChannel::send`] returns [`Error::Timeout`] then.
+pub struct ReliableChannel {
+    events: Mutex<Sender<Event>>,
+    failed: Arc<AtomicBool>,
+}
+
+impl ReliableChannel {
+    /// Create channel over transport built with the handler from [`channel_handler`],
+    /// `on_message` is invoked with every received message in order.
+    pub fn new<F>(transport: Arc<dyn IceTransport>, incoming: Incoming, on_message: F) -> Self
+    where
+        F: FnMut(Vec<u8>),
+        F: Send + 'static,
+    {
+        let Incoming { tx, rx } = incoming;
+        let connection = Connection::new(transport, Box::new(on_message));
+        let failed = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let failed = failed.clone();
+            move || {
+                if let Err(e) = connection.run(rx) {
+                    log::warn!("reliable channel failed: {}", e);
+                    failed.store(true, Ordering::Release);
+                }
+            }
+        });
+        Self {
+            events: Mutex::new(tx),
+            failed,
+        }
+    }
+
+    /// Queue message for delivery, fails with [`Error::Timeout`] once the peer is gone
+    pub fn send(&self, message: &[u8]) -> Result<()> {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(Error::MessageTooLarge);
+        }
+        if self.failed.load(Ordering::Acquire) {
+            return Err(Error::Timeout);
+        }
+        self.events
+            .lock()
+            .unwrap()
+            .send(Event::Send(message.to_vec()))
+            .map_err(|_| Error::Failed)
+    }
+}
+
+impl Drop for ReliableChannel {
+    fn drop(&mut self) {
+        let _ = self.events.lock().unwrap().send(Event::Close);
+    }
+}
+
+struct InFlight {
+    seq: u32,
+    packet: Vec<u8>,
+    sent_at: Instant,
+    retransmits: u32,
+}
+
+/// Protocol state, owned by the channel thread
+struct Connection {
+    transport: Arc<dyn IceTransport>,
+    on_message: Box<dyn FnMut(Vec<u8>) + Send + 'static>,
+    next_seq: u32,
+    queue: VecDeque<Vec<u8>>,
+    in_flight: VecDeque<InFlight>,
+    window: f64,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    expected: u32,
+    reorder: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Connection {
+    fn new(
+        transport: Arc<dyn IceTransport>,
+        on_message: Box<dyn FnMut(Vec<u8>) + Send + 'static>,
+    ) -> Self {
+        Self {
+            transport,
+            on_message,
+            next_seq: 0,
+            queue: VecDeque::new(),
+            in_flight: VecDeque::new(),
+            window: INITIAL_WINDOW,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+            expected: 0,
+            reorder: BTreeMap::new(),
+        }
+    }
+
+    /// Handle events until closed, fails with [`Error::Timeout`] if the peer stops acknowledging
+    fn run(mut self, events: Receiver<Event>) -> Result<()> {
+        loop {
+            let deadline = self.in_flight.iter().map(|p| p.sent_at + self.rto).min();
+            let event = match deadline {
+                Some(deadline) => {
+                    match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                    }
+                }
+                None => match events.recv() {
+                    Ok(event) => Some(event),
+                    Err(_) => return Ok(()),
+                },
+            };
+
+            match event {
+                Some(Event::Packet(packet)) => self.on_packet(&packet),
+                Some(Event::Send(message)) => self.queue.push_back(message),
+                Some(Event::Close) => return Ok(()),
+                None => self.on_timeout()?,
+            }
+            self.flush();
+        }
+    }
+
+    /// Send queued messages allowed by the congestion window
+    fn flush(&mut self) {
+        while (self.in_flight.len() as f64) < self.window.floor() {
+            let message = match self.queue.pop_front() {
+                Some(message) => message,
+                None => break,
+            };
+            let seq = self.next_seq;
+            self.next_seq = self.next_seq.wrapping_add(1);
+
+            let packet = encode(KIND_DATA, seq, &message);
+            // not connected yet, retransmission takes care of it
+            let _ = self.transport.send(&packet);
+            self.in_flight.push_back(InFlight {
+                seq,
+                packet,
+                sent_at: Instant::now(),
+                retransmits: 0,
+            });
+        }
+    }
+
+    /// Retransmit expired packets and back off, fails if a packet ran out of retransmissions
+    fn on_timeout(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let mut expired = false;
+        for packet in self.in_flight.iter_mut() {
+            if packet.sent_at + self.rto <= now {
+                if packet.retransmits == MAX_RETRANSMITS {
+                    return Err(Error::Timeout);
+                }
+                let _ = self.transport.send(&packet.packet);
+                packet.sent_at = now;
+                packet.retransmits += 1;
+                expired = true;
+            }
+        }
+        if expired {
+            self.window = f64::max(self.window / 2.0, 1.0);
+            self.rto = std::cmp::min(self.rto * 2, MAX_RTO);
+        }
+        Ok(())
+    }
+
+    fn on_packet(&mut self, packet: &[u8]) {
+        match decode(packet) {
+            Some((KIND_DATA, seq, payload)) => self.on_data(seq, payload),
+            Some((KIND_ACK, ack, _)) => self.on_ack(ack),
+            _ => log::debug!("malformed reliable channel packet"),
+        }
+    }
+
+    fn on_data(&mut self, seq: u32, payload: &[u8]) {
+        let ahead = seq.wrapping_sub(self.expected);
+        if ahead == 0 {
+            (self.on_message)(payload.to_vec());
+            self.expected = self.expected.wrapping_add(1);
+            while let Some(message) = self.reorder.remove(&self.expected) {
+                (self.on_message)(message);
+                self.expected = self.expected.wrapping_add(1);
+            }
+        } else if ahead < RECV_WINDOW {
+            self.reorder.insert(seq, payload.to_vec());
+        }
+        // duplicates are acknowledged again, the previous ack may be lost
+        let _ = self.transport.send(&encode(KIND_ACK, self.expected, &[]));
+    }
+
+    /// Cumulative acknowledgement, `ack` is the next sequence expected by the peer
+    fn on_ack(&mut self, ack: u32) {
+        let now = Instant::now();
+        while let Some(oldest) = self.in_flight.front() {
+            // acknowledged if it's behind ack within the sending window
+            let behind = ack.wrapping_sub(oldest.seq);
+            if behind == 0 || behind > self.in_flight.len() as u32 {
+                break;
+            }
+            if oldest.retransmits == 0 {
+                self.on_rtt(now - oldest.sent_at);
+            }
+            self.in_flight.pop_front();
+            self.window = f64::min(self.window + 1.0 / self.window, MAX_WINDOW);
+        }
+    }
+
+    /// Update retransmission timeout (RFC 6298)
+    fn on_rtt(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let rto = self.srtt.unwrap() + self.rttvar * 4;
+        self.rto = rto.clamp(MIN_RTO, MAX_RTO);
+    }
+}
+
+fn encode(kind: u8, seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_SIZE + payload.len());
+    packet.extend([MARKER, kind]);
+    packet.extend(seq.to_be_bytes());
+    packet.extend(payload);
+    packet
+}
+
+fn decode(packet: &[u8]) -> Option<(u8, u32, &[u8])> {
+    if packet.len() < HEADER_SIZE || packet[0] != MARKER {
+        return None;
+    }
+    let seq = u32::from_be_bytes([packet[2], packet[3], packet[4], packet[5]]);
+    Some((packet[1], seq, &packet[HEADER_SIZE..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::State;
+    use std::sync::mpsc::channel;
+
+    /// Transport dropping a quarter of packets
+    struct Lossy {
+        peer: Mutex<Sender<Event>>,
+        /// xorshift state
+        rng: Mutex<u32>,
+    }
+
+    impl IceTransport for Lossy {
+        fn state(&self) -> State {
+            State::Completed
+        }
+        fn local_description(&self) -> Result<String> {
+            Err(Error::NotAvailable)
+        }
+        fn gather_candidates(&self) -> Result<()> {
+            Ok(())
+        }
+        fn set_remote_description(&self, _sdp: String) -> Result<()> {
+            Ok(())
+        }
+        fn add_remote_candidate(&self, _sdp: String) -> Result<()> {
+            Ok(())
+        }
+        fn set_remote_gathering_done(&self) -> Result<()> {
+            Ok(())
+        }
+        fn send(&self, data: &[u8]) -> Result<()> {
+            let mut x = self.rng.lock().unwrap();
+            *x ^= *x << 13;
+            *x ^= *x >> 17;
+            *x ^= *x << 5;
+            if *x & 3 != 0 {
+                let _ = self.peer.lock().unwrap().send(Event::Packet(data.to_vec()));
+            }
+            Ok(())
+        }
+        fn selected_candidates(&self) -> Result<(String, String)> {
+            Err(Error::NotAvailable)
+        }
+        fn selected_addresses(&self) -> Result<(String, String)> {
+            Err(Error::NotAvailable)
+        }
+    }
+
+    #[test]
+    fn lossy() {
+        let (first_tx, first_rx) = channel();
+        let (second_tx, second_rx) = channel();
+        let transport = |peer: &Sender<Event>, seed| -> Arc<dyn IceTransport> {
+            Arc::new(Lossy {
+                peer: Mutex::new(peer.clone()),
+                rng: Mutex::new(seed),
+            })
+        };
+
+        let (messages_tx, messages_rx) = channel();
+        let first = ReliableChannel::new(
+            transport(&second_tx, 1),
+            Incoming {
+                tx: first_tx.clone(),
+                rx: first_rx,
+            },
+            |_| {},
+        );
+        let _second = ReliableChannel::new(
+            transport(&first_tx, 7),
+            Incoming {
+                tx: second_tx.clone(),
+                rx: second_rx,
+            },
+            move |message| messages_tx.send(message).unwrap(),
+        );
+
+        for i in 0..100u32 {
+            first.send(&i.to_be_bytes()).unwrap();
+        }
+        for i in 0..100u32 {
+            let message = messages_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(message, i.to_be_bytes());
+        }
+        assert_eq!(
+            first.send(&[0; MAX_MESSAGE_SIZE + 1]),
+            Err(Error::MessageTooLarge)
+        );
+    }
+
+    #[test]
+    fn dead_peer() {
+        // peer receiver is gone, nothing is acknowledged
+        let (peer, _) = channel();
+        let transport = Arc::new(Lossy {
+            peer: Mutex::new(peer),
+            rng: Mutex::new(1),
+        });
+        let mut connection = Connection::new(transport, Box::new(|_| {}));
+        connection.queue.push_back(b"hello".to_vec());
+        connection.flush();
+
+        let expire = |connection: &mut Connection| {
+            for packet in connection.in_flight.iter_mut() {
+                packet.sent_at -= MAX_RTO;
+            }
+            connection.on_timeout()
+        };
+        for _ in 0..MAX_RETRANSMITS {
+            assert_eq!(expire(&mut connection), Ok(()));
+        }
+        assert_eq!(expire(&mut connection), Err(Error::Timeout));
+
+        let (tx, _rx) = channel();
+        let channel = ReliableChannel {
+            events: Mutex::new(tx),
+            failed: Arc::new(AtomicBool::new(true)),
+        };
+        assert_eq!(channel.send(b"late"), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn handler_filter() {
+        let (tx, rx) = channel();
+        let (mut handler, incoming) =
+            channel_handler(Handler::default().recv_handler(move |p| tx.send(p.to_vec()).unwrap()));
+
+        handler.on_recv(&encode(KIND_ACK, 1, &[]), || unreachable!());
+        handler.on_recv(b"plain", || unreachable!());
+        assert!(matches!(incoming.rx.try_recv(), Ok(Event::Packet(_))));
+        assert_eq!(rx.try_recv().unwrap(), b"plain");
+    }
+}
@@ -0,0 +1,340 @@
+//! Authenticated encryption of application data with a pre-shared key.
+//!
+//! Lightweight alternative to DTLS for peers sharing a secret already, e.g. over signaling.
+//! Packets are sealed with XChaCha20-Poly1305, the nonce is a random per-channel prefix followed
+//! by a packet counter, which the receiver checks against a sliding window to drop replays.
+//! Peers take opposite [`Role`]s, which are authenticated with every packet, so packets
+//! reflected back to their sender are rejected.
+//! Channel packets are marked with a leading byte and are filtered out of the regular recv
+//! handler. There is no key exchange nor forward secrecy, a new key should be agreed per session.
+//!
+//! # Example
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use libjuice_rs::{Agent, Handler};
+//! # use libjuice_rs::secure::{channel_handler, Role, SecureChannel};
+//! let key = [7u8; 32]; // shared over signaling
+//! let (handler, incoming) = channel_handler(Handler::default());
+//! let agent = Arc::new(Agent::builder(handler).build().unwrap());
+//! let channel = SecureChannel::new(agent.clone(), &key, Role::Initiator, incoming, |message| {
+//!     println!("received {:?}", message);
+//! });
+//! // ... exchange descriptions and wait for connection
+//! channel.send(b"hello").unwrap();
+//! ```
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use crate::agent::MAX_DATAGRAM_SIZE;
+use crate::marked::{self, Event};
+use crate::{Error, Handler, IceTransport, Result};
+
+/// First byte of channel packets
+pub const MARKER: u8 = 0xFC;
+/// Random part of the nonce, fixed for the channel lifetime
+const PREFIX_SIZE: usize = 16;
+const NONCE_SIZE: usize = PREFIX_SIZE + 8;
+const TAG_SIZE: usize = 16;
+const OVERHEAD: usize = 1 + NONCE_SIZE + TAG_SIZE;
+/// Largest message carried by a single packet
+pub const MAX_MESSAGE_SIZE: usize = MAX_DATAGRAM_SIZE - OVERHEAD;
+/// Packets accepted out of order behind the newest one
+const REPLAY_WINDOW: u64 = 64;
+
+/// Side of the channel, peers must take opposite roles.
+///
+/// E.g. the peer sending the offer is the initiator and the answering one the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Side starting the session
+    Initiator,
+    /// Side answering the initiator
+    Responder,
+}
+
+impl Role {
+    fn peer(self) -> Self {
+        match self {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        }
+    }
+
+    /// Additional data authenticated with packets sealed by this role
+    fn aad(self) -> [u8; 2] {
+        [MARKER, self as u8]
+    }
+}
+
+/// Channel packets received by the agent, consumed by [`SecureChannel`].
+pub type Incoming = marked::Incoming<MARKER>;
+
+/// Route channel packets received by the agent built with returned handler to [`SecureChannel`].
+///
+/// Other packets are passed to the recv handlers of the given handler.
+pub fn channel_handler(handler: Handler) -> (Handler, Incoming) {
+    marked::route(handler)
+}
+
+/// Encrypted message channel over ICE transport.
+///
+/// Received messages are decrypted on a dedicated thread, which lives until the channel is
+/// dropped. Packets failing authentication or replayed are dropped.
+///
+/// The first authenticated packet pins the nonce prefix of the peer channel for the lifetime of
+/// this one. If the peer recreates its channel, e.g. after an ICE restart, this side must be
+/// recreated too, packets of the new peer channel are dropped otherwise.
+pub struct SecureChannel {
+    transport: Arc<dyn IceTransport>,
+    sealer: Mutex<Sealer>,
+    events: Mutex<Sender<Event>>,
+}
+
+impl SecureChannel {
+    /// Create channel over transport built with the handler from [`channel_handler`],
+    /// `on_message` is invoked with every authenticated message sent by the peer role.
+    pub fn new<F>(
+        transport: Arc<dyn IceTransport>,
+        key: &[u8; 32],
+        role: Role,
+        incoming: Incoming,
+        mut on_message: F,
+    ) -> Self
+    where
+        F: FnMut(Vec<u8>),
+        F: Send + 'static,
+    {
+        let Incoming { tx, rx } = incoming;
+        let prefix = random_prefix();
+        let mut opener = Opener::new(key, role.peer(), prefix);
+        thread::spawn(move || {
+            while let Ok(Event::Packet(packet)) = rx.recv() {
+                match opener.open(&packet) {
+                    Some(message) => on_message(message),
+                    None => log::debug!("dropping unauthenticated or replayed packet"),
+                }
+            }
+        });
+        Self {
+            transport,
+            sealer: Mutex::new(Sealer::new(key, role, prefix)),
+            events: Mutex::new(tx),
+        }
+    }
+
+    /// Encrypt and send message
+    pub fn send(&self, message: &[u8]) -> Result<()> {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(Error::MessageTooLarge);
+        }
+        let packet = self.sealer.lock().unwrap().seal(message)?;
+        self.transport.send(&packet)
+    }
+}
+
+impl Drop for SecureChannel {
+    fn drop(&mut self) {
+        let _ = self.events.lock().unwrap().send(Event::Close);
+    }
+}
+
+/// Generate random nonce prefix from the OS generator
+fn random_prefix() -> [u8; PREFIX_SIZE] {
+    let mut prefix = [0u8; PREFIX_SIZE];
+    OsRng.fill_bytes(&mut prefix);
+    prefix
+}
+
+/// Sending side: encrypts with a fresh nonce per packet
+struct Sealer {
+    cipher: XChaCha20Poly1305,
+    role: Role,
+    prefix: [u8; PREFIX_SIZE],
+    counter: u64,
+}
+
+impl Sealer {
+    fn new(key: &[u8; 32], role: Role, prefix: [u8; PREFIX_SIZE]) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+            role,
+            prefix,
+            counter: 0,
+        }
+    }
+
+    fn seal(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[..PREFIX_SIZE].copy_from_slice(&self.prefix);
+        nonce[PREFIX_SIZE..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self.counter.checked_add(1).ok_or(Error::NotAvailable)?;
+
+        let payload = Payload {
+            msg: message,
+            aad: &self.role.aad(),
+        };
+        let sealed = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Failed)?;
+        let mut packet = Vec::with_capacity(1 + NONCE_SIZE + sealed.len());
+        packet.push(MARKER);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&sealed);
+        Ok(packet)
+    }
+}
+
+/// Receiving side: authenticates, decrypts and drops replays
+struct Opener {
+    cipher: XChaCha20Poly1305,
+    /// Role of the peer, packets sealed by the other one are rejected
+    peer: Role,
+    /// Nonce prefix of the local sealer, never accepted from the peer
+    local: [u8; PREFIX_SIZE],
+    /// Nonce prefix of the peer, pinned by the first authenticated packet
+    prefix: Option<[u8; PREFIX_SIZE]>,
+    /// Newest counter seen and bitmap of the window behind it, bit 0 is the newest
+    newest: u64,
+    seen: u64,
+}
+
+impl Opener {
+    fn new(key: &[u8; 32], peer: Role, local: [u8; PREFIX_SIZE]) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+            peer,
+            local,
+            prefix: None,
+            newest: 0,
+            seen: 0,
+        }
+    }
+
+    fn open(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        if packet.len() < OVERHEAD || packet[0] != MARKER {
+            return None;
+        }
+        let (nonce, sealed) = packet[1..].split_at(NONCE_SIZE);
+        let prefix: [u8; PREFIX_SIZE] = nonce[..PREFIX_SIZE].try_into().unwrap();
+        let counter = u64::from_be_bytes(nonce[PREFIX_SIZE..].try_into().unwrap());
+        if prefix == self.local
+            || self.prefix.is_some_and(|pinned| pinned != prefix)
+            || self.is_replay(counter)
+        {
+            return None;
+        }
+
+        let payload = Payload {
+            msg: sealed,
+            aad: &self.peer.aad(),
+        };
+        let message = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .ok()?;
+        self.prefix = Some(prefix);
+        self.mark(counter);
+        Some(message)
+    }
+
+    fn is_replay(&self, counter: u64) -> bool {
+        if self.prefix.is_none() || counter > self.newest {
+            return false;
+        }
+        let behind = self.newest - counter;
+        behind >= REPLAY_WINDOW || self.seen & (1 << behind) != 0
+    }
+
+    fn mark(&mut self, counter: u64) {
+        if self.seen == 0 || counter > self.newest {
+            let shift = counter - self.newest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.newest = counter;
+        }
+        self.seen |= 1 << (self.newest - counter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn new_opener(key: &[u8; 32]) -> Opener {
+        Opener::new(key, Role::Initiator, random_prefix())
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut sealer = Sealer::new(&KEY, Role::Initiator, random_prefix());
+        let mut opener = new_opener(&KEY);
+
+        let first = sealer.seal(b"hello").unwrap();
+        assert_eq!(first.len(), 5 + OVERHEAD);
+        assert_eq!(first[0], MARKER);
+        assert_eq!(opener.open(&first), Some(b"hello".to_vec()));
+
+        let mut tampered = sealer.seal(b"world").unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(opener.open(&tampered), None);
+
+        let mut other = new_opener(&[8; 32]);
+        assert_eq!(other.open(&sealer.seal(b"key").unwrap()), None);
+
+        // another sender with the same key is not accepted once the peer is pinned
+        let mut intruder = Sealer::new(&KEY, Role::Initiator, random_prefix());
+        assert_eq!(opener.open(&intruder.seal(b"x").unwrap()), None);
+    }
+
+    #[test]
+    fn reflection() {
+        let prefix = random_prefix();
+        let mut sealer = Sealer::new(&KEY, Role::Responder, prefix);
+        let mut opener = Opener::new(&KEY, Role::Initiator, prefix);
+        // own packets sent back by an attacker
+        assert_eq!(opener.open(&sealer.seal(b"echo").unwrap()), None);
+
+        // sealed by the local role under another prefix
+        let mut mirrored = Sealer::new(&KEY, Role::Responder, random_prefix());
+        assert_eq!(opener.open(&mirrored.seal(b"echo").unwrap()), None);
+
+        let mut peer = Sealer::new(&KEY, Role::Initiator, random_prefix());
+        assert_eq!(
+            opener.open(&peer.seal(b"hi").unwrap()),
+            Some(b"hi".to_vec())
+        );
+    }
+
+    #[test]
+    fn replay() {
+        let mut sealer = Sealer::new(&KEY, Role::Initiator, random_prefix());
+        let mut opener = new_opener(&KEY);
+        let packets = (0..100u8)
+            .map(|i| sealer.seal(&[i]).unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(opener.open(&packets[0]).is_some());
+        assert!(opener.open(&packets[0]).is_none());
+        // reordered within the window
+        assert!(opener.open(&packets[10]).is_some());
+        assert!(opener.open(&packets[5]).is_some());
+        assert!(opener.open(&packets[5]).is_none());
+        assert!(opener.open(&packets[10]).is_none());
+
+        assert!(opener.open(&packets[99]).is_some());
+        // too old to tell
+        assert!(opener.open(&packets[20]).is_none());
+        assert!(opener.open(&packets[40]).is_some());
+    }
+}
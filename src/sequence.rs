@@ -0,0 +1,9 @@
+//! Crate-wide event sequence.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// Get next sequence number, unique and increasing across all agents and threads
+pub(crate) fn next() -> u64 {
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
@@ -0,0 +1,18 @@
+//! Serde helpers.
+
+/// (De)serialize [`std::ffi::CString`] as a regular string.
+pub(crate) mod cstring {
+    use std::ffi::CString;
+
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &CString, serializer: S) -> Result<S::Ok, S::Error> {
+        let s = value.to_str().map_err(S::Error::custom)?;
+        serializer.serialize_str(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CString, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        CString::new(s).map_err(D::Error::custom)
+    }
+}
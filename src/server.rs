@@ -9,9 +9,14 @@ use libjuice_sys as sys;
 use crate::log::ensure_logging;
 use crate::{Error, Result};
 
+/// TURN user credentials.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Credentials {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::cstring"))]
     username: CString,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_util::cstring"))]
     password: CString,
+    #[cfg_attr(feature = "serde", serde(default))]
     quota: Option<i32>,
 }
 
@@ -201,4 +206,21 @@ mod tests {
             .ok()
             .unwrap();
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn credentials_serde() {
+        let creds: Credentials =
+            serde_json::from_str(r#"{"username": "a", "password": "b"}"#).unwrap();
+        assert_eq!(creds.username.as_bytes(), b"a");
+        assert_eq!(creds.quota, None);
+
+        let json = serde_json::to_string(&creds).unwrap();
+        assert_eq!(json, r#"{"username":"a","password":"b","quota":null}"#);
+
+        assert!(
+            serde_json::from_str::<Credentials>(r#"{"username": "a\u0000", "password": "b"}"#)
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,136 @@
+//! Descriptions and candidates exchange with the remote agent.
+//!
+//! # Example
+//! ```no_run
+//! # use std::thread;
+//! # use std::time::Duration;
+//! # use libjuice_rs::{Agent, Handler};
+//! # use libjuice_rs::signaling::ChannelSignaling;
+//! let (mut first_signaling, mut second_signaling) = ChannelSignaling::pair();
+//! let first = Agent::builder(Handler::default()).build().unwrap();
+//! let second = Agent::builder(Handler::default()).build().unwrap();
+//!
+//! let timeout = Duration::from_secs(10);
+//! let peer = thread::spawn(move || second.negotiate(&mut second_signaling, timeout));
+//! first.negotiate(&mut first_signaling, timeout).unwrap();
+//! peer.join().unwrap().unwrap();
+//! ```
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::{Error, Result};
+
+#[cfg(feature = "signaling-ws")]
+pub mod ws;
+
+/// Candidate line sent once local gathering is done (RFC 8840)
+pub const END_OF_CANDIDATES: &str = "a=end-of-candidates";
+
+/// Signaling transport used by [`crate::Agent::negotiate`].
+///
+/// Receive methods wait up to `timeout` and return `Ok(None)` if nothing has arrived.
+pub trait Signaling {
+    /// Send local description
+    fn send_description(&mut self, sdp: &str) -> Result<()>;
+
+    /// Receive remote description
+    fn recv_description(&mut self, timeout: Duration) -> Result<Option<String>>;
+
+    /// Send local candidate, [`END_OF_CANDIDATES`] when gathering is done
+    fn send_candidate(&mut self, sdp: &str) -> Result<()>;
+
+    /// Receive remote candidate
+    fn recv_candidate(&mut self, timeout: Duration) -> Result<Option<String>>;
+}
+
+/// Local events forwarded to the negotiation in progress
+pub(crate) enum Event {
+    Candidate(String),
+    GatheringDone,
+}
+
+/// In-memory [`Signaling`] between agents of the same process.
+pub struct ChannelSignaling {
+    descriptions: (Sender<String>, Receiver<String>),
+    candidates: (Sender<String>, Receiver<String>),
+}
+
+impl ChannelSignaling {
+    /// Create two connected ends
+    pub fn pair() -> (Self, Self) {
+        let (first_descriptions, second_descriptions) = (channel(), channel());
+        let (first_candidates, second_candidates) = (channel(), channel());
+        let first = Self {
+            descriptions: (first_descriptions.0, second_descriptions.1),
+            candidates: (first_candidates.0, second_candidates.1),
+        };
+        let second = Self {
+            descriptions: (second_descriptions.0, first_descriptions.1),
+            candidates: (second_candidates.0, first_candidates.1),
+        };
+        (first, second)
+    }
+}
+
+/// Receive message, failing if the other end is gone
+fn recv(rx: &Receiver<String>, timeout: Duration) -> Result<Option<String>> {
+    match rx.recv_timeout(timeout) {
+        Ok(message) => Ok(Some(message)),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err(Error::Failed),
+    }
+}
+
+impl Signaling for ChannelSignaling {
+    fn send_description(&mut self, sdp: &str) -> Result<()> {
+        self.descriptions
+            .0
+            .send(sdp.to_string())
+            .map_err(|_| Error::Failed)
+    }
+
+    fn recv_description(&mut self, timeout: Duration) -> Result<Option<String>> {
+        recv(&self.descriptions.1, timeout)
+    }
+
+    fn send_candidate(&mut self, sdp: &str) -> Result<()> {
+        self.candidates
+            .0
+            .send(sdp.to_string())
+            .map_err(|_| Error::Failed)
+    }
+
+    fn recv_candidate(&mut self, timeout: Duration) -> Result<Option<String>> {
+        recv(&self.candidates.1, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_pair() {
+        let (mut first, mut second) = ChannelSignaling::pair();
+        let timeout = Duration::from_millis(10);
+
+        first.send_candidate("a=candidate:1").unwrap();
+        first.send_description("v=0").unwrap();
+        assert_eq!(second.recv_description(timeout), Ok(Some("v=0".into())));
+        assert_eq!(second.recv_description(timeout), Ok(None));
+        assert_eq!(
+            second.recv_candidate(timeout),
+            Ok(Some("a=candidate:1".into()))
+        );
+
+        second.send_candidate(END_OF_CANDIDATES).unwrap();
+        assert_eq!(
+            first.recv_candidate(timeout),
+            Ok(Some(END_OF_CANDIDATES.into()))
+        );
+
+        drop(second);
+        assert_eq!(first.recv_candidate(timeout), Err(Error::Failed));
+        assert_eq!(first.send_description("v=0"), Err(Error::Failed));
+    }
+}
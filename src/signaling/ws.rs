@@ -0,0 +1,364 @@
+//! WebSocket [`Signaling`] exchanging JSON messages, with a relay server for demos and tests.
+//!
+//! Messages are `{"type":"description","sdp":"..."}` and
+//! `{"type":"candidate","candidate":"..."}` text frames. [`WsSignalingServer`] relays messages
+//! between clients connected with the same room, clients joining later receive messages sent
+//! to the room before.
+//!
+//! # Example
+//! ```no_run
+//! # use std::thread;
+//! # use std::time::Duration;
+//! # use libjuice_rs::{Agent, Handler};
+//! # use libjuice_rs::signaling::ws::{WsSignaling, WsSignalingServer};
+//! let server = WsSignalingServer::bind("127.0.0.1:0").unwrap();
+//! let addr = server.local_addr();
+//! let timeout = Duration::from_secs(10);
+//!
+//! let peer = thread::spawn(move || {
+//!     let agent = Agent::builder(Handler::default()).build().unwrap();
+//!     let mut signaling = WsSignaling::connect(addr, "demo").unwrap();
+//!     agent.negotiate(&mut signaling, timeout).map(|_| agent)
+//! });
+//!
+//! let agent = Agent::builder(Handler::default()).build().unwrap();
+//! let mut signaling = WsSignaling::connect(addr, "demo").unwrap();
+//! agent.negotiate(&mut signaling, timeout).unwrap();
+//! let peer = peer.join().unwrap().unwrap();
+//! ```
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::result::Result as StdResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::{Message, WebSocket};
+
+use crate::signaling::Signaling;
+use crate::{Error, Result};
+
+/// Server sockets polling period
+const POLL: Duration = Duration::from_millis(20);
+/// Time allowed to a client to complete the WebSocket upgrade
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Messages replayed to joining peers, older ones are dropped
+const MAX_HISTORY: usize = 256;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SignalingMessage {
+    Description { sdp: String },
+    Candidate { candidate: String },
+}
+
+fn failed<E: Display>(e: E) -> Error {
+    log::warn!("websocket signaling failed: {}", e);
+    Error::Failed
+}
+
+/// Whether read failed only because of the socket timeout
+fn is_timeout(e: &tungstenite::Error) -> bool {
+    match e {
+        tungstenite::Error::Io(e) => {
+            matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        }
+        _ => false,
+    }
+}
+
+/// [`Signaling`] client of [`WsSignalingServer`] or a compatible server.
+pub struct WsSignaling {
+    socket: WebSocket<TcpStream>,
+    descriptions: VecDeque<String>,
+    candidates: VecDeque<String>,
+}
+
+impl WsSignaling {
+    /// Connect to the server and join the `room`, i.e. request path
+    pub fn connect<A: ToSocketAddrs>(addr: A, room: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).map_err(failed)?;
+        let url = format!("ws://{}/{}", stream.peer_addr().map_err(failed)?, room);
+        let (socket, _) = tungstenite::client(url, stream).map_err(failed)?;
+        Ok(Self {
+            socket,
+            descriptions: VecDeque::new(),
+            candidates: VecDeque::new(),
+        })
+    }
+
+    fn send(&mut self, message: SignalingMessage) -> Result<()> {
+        let text = serde_json::to_string(&message).map_err(failed)?;
+        self.socket.send(Message::text(text)).map_err(failed)
+    }
+
+    /// Read messages until the queue picked has one or timeout expires
+    fn recv<F>(&mut self, timeout: Duration, queue: F) -> Result<Option<String>>
+    where
+        F: Fn(&mut Self) -> &mut VecDeque<String>,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = queue(self).pop_front() {
+                return Ok(Some(message));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            self.socket
+                .get_ref()
+                .set_read_timeout(Some(left))
+                .map_err(failed)?;
+            match self.socket.read() {
+                Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                    Ok(SignalingMessage::Description { sdp }) => self.descriptions.push_back(sdp),
+                    Ok(SignalingMessage::Candidate { candidate }) => {
+                        self.candidates.push_back(candidate)
+                    }
+                    Err(e) => log::warn!("unexpected signaling message: {}", e),
+                },
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => return Err(failed(e)),
+            }
+        }
+    }
+}
+
+impl Signaling for WsSignaling {
+    fn send_description(&mut self, sdp: &str) -> Result<()> {
+        self.send(SignalingMessage::Description {
+            sdp: sdp.to_string(),
+        })
+    }
+
+    fn recv_description(&mut self, timeout: Duration) -> Result<Option<String>> {
+        self.recv(timeout, |s| &mut s.descriptions)
+    }
+
+    fn send_candidate(&mut self, sdp: &str) -> Result<()> {
+        self.send(SignalingMessage::Candidate {
+            candidate: sdp.to_string(),
+        })
+    }
+
+    fn recv_candidate(&mut self, timeout: Duration) -> Result<Option<String>> {
+        self.recv(timeout, |s| &mut s.candidates)
+    }
+}
+
+#[derive(Default)]
+struct Room {
+    peers: Vec<(u64, Sender<String>)>,
+    /// Latest messages sent to the room, replayed to joining peers
+    history: VecDeque<String>,
+}
+
+impl Room {
+    fn record(&mut self, text: String) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(text);
+    }
+}
+
+type Rooms = Arc<Mutex<HashMap<String, Room>>>;
+
+/// Minimal WebSocket relay server for [`WsSignaling`] clients.
+///
+/// Serves clients on background threads until dropped.
+pub struct WsSignalingServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl WsSignalingServer {
+    /// Start listening on given address
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let listener = TcpListener::bind(addr).map_err(failed)?;
+        listener.set_nonblocking(true).map_err(failed)?;
+        let addr = listener.local_addr().map_err(failed)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let stop = stop.clone();
+            move || accept(listener, stop)
+        });
+        Ok(Self { addr, stop })
+    }
+
+    /// Get listening address
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for WsSignalingServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed)
+    }
+}
+
+fn accept(listener: TcpListener, stop: Arc<AtomicBool>) {
+    let rooms = Rooms::default();
+    let mut next_id = 0;
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                log::debug!("signaling client {} connected", peer);
+                let (id, rooms, stop) = (next_id, rooms.clone(), stop.clone());
+                next_id += 1;
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, id, rooms, stop) {
+                        log::debug!("signaling client {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL),
+            Err(e) => log::warn!("signaling server accept failed: {}", e),
+        }
+    }
+}
+
+/// Takes room from the request path
+struct RoomPath<'a>(&'a mut String);
+
+impl Callback for RoomPath<'_> {
+    fn on_request(
+        self,
+        request: &Request,
+        response: Response,
+    ) -> StdResult<Response, ErrorResponse> {
+        *self.0 = request.uri().path().trim_start_matches('/').to_string();
+        Ok(response)
+    }
+}
+
+fn serve(stream: TcpStream, id: u64, rooms: Rooms, stop: Arc<AtomicBool>) -> Result<()> {
+    stream.set_nonblocking(false).map_err(failed)?;
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(failed)?;
+    let mut room = String::new();
+    let mut socket = tungstenite::accept_hdr(stream, RoomPath(&mut room)).map_err(failed)?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL))
+        .map_err(failed)?;
+
+    let (tx, rx) = channel();
+    {
+        let mut rooms = rooms.lock().unwrap();
+        let room = rooms.entry(room.clone()).or_default();
+        for message in &room.history {
+            let _ = tx.send(message.clone());
+        }
+        room.peers.push((id, tx));
+    }
+
+    let res = (|| {
+        while !stop.load(Ordering::Relaxed) {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let mut rooms = rooms.lock().unwrap();
+                    let room = rooms.get_mut(&room).expect("joined room");
+                    for (_, peer) in room.peers.iter().filter(|(peer, _)| *peer != id) {
+                        let _ = peer.send(text.clone());
+                    }
+                    room.record(text);
+                }
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(e) => return Err(failed(e)),
+            }
+            for text in rx.try_iter() {
+                socket.send(Message::text(text)).map_err(failed)?;
+            }
+        }
+        Ok(())
+    })();
+
+    let mut rooms = rooms.lock().unwrap();
+    if let Some(joined) = rooms.get_mut(&room) {
+        joined.peers.retain(|(peer, _)| *peer != id);
+        if joined.peers.is_empty() {
+            rooms.remove(&room);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay() {
+        crate::test_util::logger_init();
+
+        let server = WsSignalingServer::bind("127.0.0.1:0").unwrap();
+        let timeout = Duration::from_secs(2);
+
+        let mut first = WsSignaling::connect(server.local_addr(), "room").unwrap();
+        let mut other = WsSignaling::connect(server.local_addr(), "other").unwrap();
+        first.send_description("v=0").unwrap();
+        first.send_candidate("a=candidate:1").unwrap();
+
+        // joins after the messages were sent
+        let mut second = WsSignaling::connect(server.local_addr(), "room").unwrap();
+        assert_eq!(
+            second.recv_candidate(timeout),
+            Ok(Some("a=candidate:1".into()))
+        );
+        assert_eq!(second.recv_description(timeout), Ok(Some("v=0".into())));
+        second.send_description("v=1").unwrap();
+        assert_eq!(first.recv_description(timeout), Ok(Some("v=1".into())));
+
+        let short = Duration::from_millis(50);
+        assert_eq!(first.recv_candidate(short), Ok(None));
+        assert_eq!(other.recv_description(short), Ok(None));
+    }
+
+    #[test]
+    fn slow_handshake() {
+        let server = WsSignalingServer::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        // upgrade request arrives well after the polling period
+        thread::sleep(POLL * 5);
+        let url = format!("ws://{}/room", server.local_addr());
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        socket.send(Message::text("{}")).unwrap();
+    }
+
+    #[test]
+    fn history_cap() {
+        let mut room = Room::default();
+        for i in 0..MAX_HISTORY + 10 {
+            room.record(i.to_string());
+        }
+        assert_eq!(room.history.len(), MAX_HISTORY);
+        assert_eq!(room.history.front(), Some(&"10".to_string()));
+    }
+
+    #[test]
+    fn message_format() {
+        let message: SignalingMessage =
+            serde_json::from_str(r#"{"type":"candidate","candidate":"a=candidate:1"}"#).unwrap();
+        assert_eq!(
+            message,
+            SignalingMessage::Candidate {
+                candidate: "a=candidate:1".into()
+            }
+        );
+        let json = serde_json::to_string(&SignalingMessage::Description { sdp: "v=0".into() });
+        assert_eq!(json.unwrap(), r#"{"type":"description","sdp":"v=0"}"#);
+    }
+}
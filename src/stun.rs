@@ -0,0 +1,240 @@
+//! Minimal STUN client.
+//!
+//! Allows to learn the server reflexive address without building an [`crate::Agent`].
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const FAMILY_IPV4: u8 = 0x01;
+const FAMILY_IPV6: u8 = 0x02;
+/// Requests sent during the timeout
+const ATTEMPTS: u32 = 3;
+
+type TransactionId = [u8; 12];
+
+/// Discover public (server reflexive) address with STUN binding request.
+///
+/// # Example
+/// ```no_run
+/// # use std::time::Duration;
+/// # use libjuice_rs::stun;
+/// let addr = stun::discover_public_address("stun.l.google.com:19302", Duration::from_secs(3))
+///     .unwrap();
+/// println!("public address: {}", addr);
+/// ```
+pub fn discover_public_address<A: ToSocketAddrs>(
+    server: A,
+    timeout: Duration,
+) -> Result<SocketAddr> {
+    let server = server
+        .to_socket_addrs()
+        .map_err(|_| Error::InvalidArgument)?
+        .next()
+        .ok_or(Error::InvalidArgument)?;
+
+    let bind: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).map_err(|_| Error::Failed)?;
+
+    let id = transaction_id();
+    let request = binding_request(&id);
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 1024];
+
+    for _ in 0..ATTEMPTS {
+        socket
+            .send_to(&request, server)
+            .map_err(|_| Error::Failed)?;
+
+        // retransmit after even share of the timeout
+        let retransmit = Instant::now() + timeout / ATTEMPTS;
+        loop {
+            let now = Instant::now();
+            let wait = std::cmp::min(retransmit, deadline).saturating_duration_since(now);
+            if wait.is_zero() {
+                break;
+            }
+            socket
+                .set_read_timeout(Some(wait))
+                .map_err(|_| Error::Failed)?;
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) if from == server => {
+                    if let Some(addr) = parse_binding_response(&buf[..len], &id) {
+                        return Ok(addr);
+                    }
+                }
+                Ok(_) => continue,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(_) => return Err(Error::Failed),
+            }
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+
+    Err(Error::NotAvailable)
+}
+
+/// Generate random transaction id
+fn transaction_id() -> TransactionId {
+    let mut id = [0u8; 12];
+    let state = RandomState::new();
+    for (i, chunk) in id.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        let bytes = hasher.finish().to_be_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+    id
+}
+
+/// Encode binding request without attributes
+fn binding_request(id: &TransactionId) -> [u8; HEADER_LEN] {
+    let mut msg = [0u8; HEADER_LEN];
+    msg[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    msg[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    msg[8..20].copy_from_slice(id);
+    msg
+}
+
+/// Parse binding success response, returns mapped address
+fn parse_binding_response(msg: &[u8], id: &TransactionId) -> Option<SocketAddr> {
+    if msg.len() < HEADER_LEN {
+        return None;
+    }
+    let kind = u16::from_be_bytes([msg[0], msg[1]]);
+    let len = u16::from_be_bytes([msg[2], msg[3]]) as usize;
+    let cookie = u32::from_be_bytes([msg[4], msg[5], msg[6], msg[7]]);
+    if kind != BINDING_SUCCESS || cookie != MAGIC_COOKIE || &msg[8..20] != id {
+        return None;
+    }
+    let attrs = msg.get(HEADER_LEN..HEADER_LEN + len)?;
+
+    let mut mapped = None;
+    let mut offset = 0;
+    while offset + 4 <= attrs.len() {
+        let kind = u16::from_be_bytes([attrs[offset], attrs[offset + 1]]);
+        let len = u16::from_be_bytes([attrs[offset + 2], attrs[offset + 3]]) as usize;
+        let value = attrs.get(offset + 4..offset + 4 + len)?;
+        match kind {
+            ATTR_XOR_MAPPED_ADDRESS => return parse_address(value, Some(id)),
+            ATTR_MAPPED_ADDRESS => mapped = parse_address(value, None),
+            _ => {}
+        }
+        // attributes are padded to 4 bytes
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+    mapped
+}
+
+/// Parse (XOR-)MAPPED-ADDRESS value, xor-ed if transaction id given
+fn parse_address(value: &[u8], xor: Option<&TransactionId>) -> Option<SocketAddr> {
+    let mut key = [0u8; 16];
+    if let Some(id) = xor {
+        key[0..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        key[4..16].copy_from_slice(id);
+    }
+
+    let family = *value.get(1)?;
+    let port = u16::from_be_bytes([*value.get(2)? ^ key[0], *value.get(3)? ^ key[1]]);
+    let ip = match family {
+        FAMILY_IPV4 => {
+            let mut octets = [0u8; 4];
+            for (i, b) in octets.iter_mut().enumerate() {
+                *b = value.get(4 + i)? ^ key[i];
+            }
+            IpAddr::from(octets)
+        }
+        FAMILY_IPV6 => {
+            let mut octets = [0u8; 16];
+            for (i, b) in octets.iter_mut().enumerate() {
+                *b = value.get(4 + i)? ^ key[i];
+            }
+            IpAddr::from(octets)
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Encode binding response with XOR-MAPPED-ADDRESS
+    fn binding_response(id: &TransactionId, addr: &SocketAddr) -> Vec<u8> {
+        let mut key = [0u8; 16];
+        key[0..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        key[4..16].copy_from_slice(id);
+
+        let (family, ip) = match addr.ip() {
+            IpAddr::V4(ip) => (FAMILY_IPV4, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (FAMILY_IPV6, ip.octets().to_vec()),
+        };
+        let mut value = vec![0, family];
+        value.extend((addr.port() ^ (MAGIC_COOKIE >> 16) as u16).to_be_bytes());
+        value.extend(ip.iter().zip(key.iter()).map(|(a, k)| a ^ k));
+
+        let mut msg = vec![];
+        msg.extend(BINDING_SUCCESS.to_be_bytes());
+        msg.extend(((value.len() + 4) as u16).to_be_bytes());
+        msg.extend(MAGIC_COOKIE.to_be_bytes());
+        msg.extend(id);
+        msg.extend(ATTR_XOR_MAPPED_ADDRESS.to_be_bytes());
+        msg.extend((value.len() as u16).to_be_bytes());
+        msg.extend(value);
+        msg
+    }
+
+    #[test]
+    fn parse() {
+        let id = transaction_id();
+        for addr in ["203.0.113.7:5000", "[2001:db8::1]:6000"] {
+            let addr = addr.parse().unwrap();
+            let response = binding_response(&id, &addr);
+            assert_eq!(parse_binding_response(&response, &id), Some(addr));
+            assert_eq!(parse_binding_response(&response, &[0; 12]), None);
+            assert_eq!(parse_binding_response(&response[..24], &id), None);
+        }
+    }
+
+    #[test]
+    fn discover() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (len, from) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(len, HEADER_LEN);
+            let mut id = [0u8; 12];
+            id.copy_from_slice(&buf[8..20]);
+            server.send_to(&binding_response(&id, &from), from).unwrap();
+            from
+        });
+
+        let addr = discover_public_address(server_addr, Duration::from_secs(1)).unwrap();
+        assert_eq!(addr, handle.join().unwrap());
+    }
+
+    #[test]
+    fn timeout() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let res = discover_public_address(server.local_addr().unwrap(), Duration::from_millis(300));
+        assert_eq!(res, Err(Error::NotAvailable));
+    }
+}
@@ -0,0 +1,134 @@
+//! ICE transport abstraction.
+use std::sync::Arc;
+
+use crate::{Agent, Result, State};
+
+/// Object safe interface of an ICE agent.
+///
+/// Implemented by [`Agent`], allows code built on top of it to accept `Arc<dyn IceTransport>`
+/// and be tested with a mock implementation or swapped to another ICE stack.
+///
+/// # Example
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use libjuice_rs::{Agent, Handler, IceTransport, State};
+/// struct Session {
+///     transport: Arc<dyn IceTransport>,
+/// }
+///
+/// impl Session {
+///     fn ping(&self) -> libjuice_rs::Result<()> {
+///         if self.transport.state() == State::Completed {
+///             self.transport.send(b"ping")?;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let agent = Agent::builder(Handler::default()).build().unwrap();
+/// let session = Session { transport: Arc::new(agent) };
+/// ```
+pub trait IceTransport: Send + Sync {
+    /// Get ICE state
+    fn state(&self) -> State;
+
+    /// Get local sdp
+    fn local_description(&self) -> Result<String>;
+
+    /// Start ICE candidates gathering
+    fn gather_candidates(&self) -> Result<()>;
+
+    /// Set remote description
+    fn set_remote_description(&self, sdp: String) -> Result<()>;
+
+    /// Add remote candidate
+    fn add_remote_candidate(&self, sdp: String) -> Result<()>;
+
+    /// Signal remote candidates exhausted
+    fn set_remote_gathering_done(&self) -> Result<()>;
+
+    /// Send packet to remote endpoint
+    fn send(&self, data: &[u8]) -> Result<()>;
+
+    /// Get selected candidates pair (local,remote)
+    fn selected_candidates(&self) -> Result<(String, String)>;
+
+    /// Get selected addresses pair (local,remote)
+    fn selected_addresses(&self) -> Result<(String, String)>;
+}
+
+impl IceTransport for Agent {
+    fn state(&self) -> State {
+        self.get_state()
+    }
+
+    fn local_description(&self) -> Result<String> {
+        self.get_local_description()
+    }
+
+    fn gather_candidates(&self) -> Result<()> {
+        Agent::gather_candidates(self)
+    }
+
+    fn set_remote_description(&self, sdp: String) -> Result<()> {
+        Agent::set_remote_description(self, sdp)
+    }
+
+    fn add_remote_candidate(&self, sdp: String) -> Result<()> {
+        Agent::add_remote_candidate(self, sdp)
+    }
+
+    fn set_remote_gathering_done(&self) -> Result<()> {
+        Agent::set_remote_gathering_done(self)
+    }
+
+    fn send(&self, data: &[u8]) -> Result<()> {
+        Agent::send(self, data)
+    }
+
+    fn selected_candidates(&self) -> Result<(String, String)> {
+        self.get_selected_candidates()
+    }
+
+    fn selected_addresses(&self) -> Result<(String, String)> {
+        self.get_selected_addresses()
+    }
+}
+
+impl<T: IceTransport + ?Sized> IceTransport for Arc<T> {
+    fn state(&self) -> State {
+        (**self).state()
+    }
+
+    fn local_description(&self) -> Result<String> {
+        (**self).local_description()
+    }
+
+    fn gather_candidates(&self) -> Result<()> {
+        (**self).gather_candidates()
+    }
+
+    fn set_remote_description(&self, sdp: String) -> Result<()> {
+        (**self).set_remote_description(sdp)
+    }
+
+    fn add_remote_candidate(&self, sdp: String) -> Result<()> {
+        (**self).add_remote_candidate(sdp)
+    }
+
+    fn set_remote_gathering_done(&self) -> Result<()> {
+        (**self).set_remote_gathering_done()
+    }
+
+    fn send(&self, data: &[u8]) -> Result<()> {
+        (**self).send(data)
+    }
+
+    fn selected_candidates(&self) -> Result<(String, String)> {
+        (**self).selected_candidates()
+    }
+
+    fn selected_addresses(&self) -> Result<(String, String)> {
+        (**self).selected_addresses()
+    }
+}
@@ -0,0 +1,211 @@
+//! Adapter for the [webrtc](https://crates.io/crates/webrtc) crate stack.
+//!
+//! [`JuiceConn`] implements [`webrtc_util::Conn`] on top of an established [`crate::Agent`] or any
+//! other [`IceTransport`], so it can be used as the underlying transport of webrtc-rs DTLS/SCTP
+//! instead of its own ICE agent.
+use std::any::Any;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use webrtc_util::{Conn, Error};
+
+use crate::agent::parse_address;
+use crate::{Handler, IceTransport};
+
+/// Packets received by the agent, consumed by [`JuiceConn`].
+pub struct Incoming {
+    tx: UnboundedSender<Option<Vec<u8>>>,
+    rx: UnboundedReceiver<Option<Vec<u8>>>,
+}
+
+/// Route packets received by the agent built with returned handler to [`JuiceConn`].
+///
+/// Replaces the recv handler of the given handler, other handlers stay untouched.
+pub fn conn_handler(handler: Handler) -> (Handler, Incoming) {
+    let (tx, rx) = unbounded_channel();
+    let packets = tx.clone();
+    let handler = handler.recv_handler(move |packet| {
+        let _ = packets.send(Some(packet.to_vec()));
+    });
+    (handler, Incoming { tx, rx })
+}
+
+/// [`webrtc_util::Conn`] implementation over ICE transport.
+///
+/// # Example
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use libjuice_rs::{Agent, Handler};
+/// # use libjuice_rs::webrtc::{conn_handler, JuiceConn};
+/// let (handler, incoming) = conn_handler(Handler::default());
+/// let agent = Arc::new(Agent::builder(handler).build().unwrap());
+/// // ... exchange descriptions and wait for connection
+/// let conn: Arc<dyn webrtc_util::Conn + Send + Sync> = Arc::new(JuiceConn::new(agent, incoming));
+/// ```
+pub struct JuiceConn {
+    agent: Arc<dyn IceTransport>,
+    /// Packets, `None` wakes up the reader on close
+    incoming: Mutex<UnboundedReceiver<Option<Vec<u8>>>>,
+    closer: UnboundedSender<Option<Vec<u8>>>,
+    closed: AtomicBool,
+}
+
+impl JuiceConn {
+    /// Create connection over transport built with the handler from [`conn_handler`]
+    pub fn new(agent: Arc<dyn IceTransport>, incoming: Incoming) -> Self {
+        Self {
+            agent,
+            incoming: Mutex::new(incoming.rx),
+            closer: incoming.tx,
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Get underlying transport
+    pub fn agent(&self) -> &Arc<dyn IceTransport> {
+        &self.agent
+    }
+
+    fn selected_addresses(&self) -> Option<(SocketAddr, SocketAddr)> {
+        let (local, remote) = self.agent.selected_addresses().ok()?;
+        Some((parse_address(&local)?, parse_address(&remote)?))
+    }
+}
+
+#[async_trait]
+impl Conn for JuiceConn {
+    /// ICE agent is connected by the negotiation, nothing to do
+    async fn connect(&self, _addr: SocketAddr) -> webrtc_util::Result<()> {
+        Ok(())
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> webrtc_util::Result<usize> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(Error::ErrUseClosedNetworkConn);
+        }
+        let mut incoming = self.incoming.lock().await;
+        let packet = match incoming.recv().await {
+            Some(Some(packet)) => packet,
+            _ => {
+                incoming.close();
+                return Err(Error::ErrUseClosedNetworkConn);
+            }
+        };
+        if packet.len() > buf.len() {
+            return Err(Error::ErrBufferShort);
+        }
+        buf[..packet.len()].copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> webrtc_util::Result<(usize, SocketAddr)> {
+        let len = self.recv(buf).await?;
+        let remote = self.remote_addr().ok_or(Error::ErrNoRemAddr)?;
+        Ok((len, remote))
+    }
+
+    async fn send(&self, buf: &[u8]) -> webrtc_util::Result<usize> {
+        self.agent
+            .send(buf)
+            .map_err(|e| Error::Other(e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    /// Packets are always sent to the selected remote candidate
+    async fn send_to(&self, buf: &[u8], _target: SocketAddr) -> webrtc_util::Result<usize> {
+        self.send(buf).await
+    }
+
+    fn local_addr(&self) -> webrtc_util::Result<SocketAddr> {
+        self.selected_addresses()
+            .map(|(local, _)| local)
+            .ok_or(Error::ErrLocAddr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.selected_addresses().map(|(_, remote)| remote)
+    }
+
+    /// Pending [`Conn::recv`] is woken up, the reader may hold the receiver lock meanwhile
+    async fn close(&self) -> webrtc_util::Result<()> {
+        if !self.closed.swap(true, Ordering::AcqRel) {
+            let _ = self.closer.send(None);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Result, State};
+    use std::time::Duration;
+
+    struct Idle;
+
+    impl IceTransport for Idle {
+        fn state(&self) -> State {
+            State::Connected
+        }
+        fn local_description(&self) -> Result<String> {
+            Err(crate::Error::NotAvailable)
+        }
+        fn gather_candidates(&self) -> Result<()> {
+            Ok(())
+        }
+        fn set_remote_description(&self, _sdp: String) -> Result<()> {
+            Ok(())
+        }
+        fn add_remote_candidate(&self, _sdp: String) -> Result<()> {
+            Ok(())
+        }
+        fn set_remote_gathering_done(&self) -> Result<()> {
+            Ok(())
+        }
+        fn send(&self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+        fn selected_candidates(&self) -> Result<(String, String)> {
+            Err(crate::Error::NotAvailable)
+        }
+        fn selected_addresses(&self) -> Result<(String, String)> {
+            Err(crate::Error::NotAvailable)
+        }
+    }
+
+    #[tokio::test]
+    async fn close_pending_recv() {
+        let (mut handler, incoming) = conn_handler(Handler::default());
+        let conn = Arc::new(JuiceConn::new(Arc::new(Idle), incoming));
+
+        handler.on_recv(b"first", || unreachable!());
+        let mut buf = [0; 16];
+        assert_eq!(conn.recv(&mut buf).await.unwrap(), 5);
+
+        let reader = tokio::spawn({
+            let conn = conn.clone();
+            async move { conn.recv(&mut [0; 16]).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // the handler keeps the sender alive, close must not wait for a packet
+        tokio::time::timeout(Duration::from_secs(1), conn.close())
+            .await
+            .unwrap()
+            .unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(res, Err(Error::ErrUseClosedNetworkConn)));
+        assert!(conn.recv(&mut buf).await.is_err());
+        drop(handler);
+    }
+}
@@ -1,9 +1,12 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver};
-use std::sync::{Arc, Barrier};
+use std::sync::{Arc, Barrier, OnceLock};
 use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use libjuice_rs::{Agent, Handler, State};
+use libjuice_rs::signaling::ChannelSignaling;
+use libjuice_rs::{Agent, CandidateType, Handler, NegotiationState, State, WeakAgent};
 
 include!("../src/test_util.rs");
 
@@ -95,6 +98,58 @@ fn connectivity_no_trickle() {
     );
 }
 
+#[test]
+fn recv_meta() {
+    logger_init();
+
+    let (done_tx, done_rx) = channel();
+    let (first_tx, first_rx) = channel();
+    let first_handler = Handler::default()
+        .gathering_done_handler({
+            let done_tx = done_tx.clone();
+            move || done_tx.send(()).unwrap()
+        })
+        .recv_handler_with_meta(move |packet, meta| {
+            let _ = first_tx.send((packet.to_vec(), meta));
+        });
+    let second_handler =
+        Handler::default().gathering_done_handler(move || done_tx.send(()).unwrap());
+
+    let bind = "127.0.0.1".parse().unwrap();
+    let first = Agent::builder(first_handler)
+        .with_bind_address(&bind)
+        .build()
+        .unwrap();
+    let second = Agent::builder(second_handler)
+        .with_bind_address(&bind)
+        .build()
+        .unwrap();
+
+    first.gather_candidates().unwrap();
+    second.gather_candidates().unwrap();
+    for _ in 0..2 {
+        done_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+    second
+        .set_remote_description(first.get_local_description().unwrap())
+        .unwrap();
+    first
+        .set_remote_description(second.get_local_description().unwrap())
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    assert_eq!(first.get_state(), State::Completed);
+
+    let before = Instant::now();
+    second.send(b"meta").unwrap();
+    let (packet, meta) = first_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(packet, b"meta");
+    assert!(meta.timestamp >= before);
+    assert!(!meta.via_relay);
+    let (_, remote, _, _) = first.selected_pair().unwrap();
+    assert_eq!(meta.remote, remote);
+}
+
 enum TrickleEvent {
     Candidate(String),
     Eof,
@@ -249,3 +304,251 @@ fn connectivity_trickle() {
     handle1.join().unwrap();
     handle2.join().unwrap();
 }
+
+#[test]
+fn connectivity_loopback() {
+    logger_init();
+
+    let (tx, rx) = channel();
+    let handler = Handler::default().recv_handler(move |packet| {
+        let _ = tx.send(packet.to_vec());
+    });
+    let (first, _second) = Agent::pair_loopback(Handler::default(), handler).unwrap();
+
+    let (local, remote, local_kind, remote_kind) = first.selected_pair().unwrap();
+    log::info!("loopback selected pair: {} -> {}", local, remote);
+    assert!(local.ip().is_loopback() && remote.ip().is_loopback());
+    assert_eq!(local_kind, CandidateType::Host);
+    assert_eq!(remote_kind, CandidateType::Host);
+
+    first.send("hello".as_bytes()).unwrap();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok("hello".into()));
+}
+
+#[test]
+fn send_batch() {
+    logger_init();
+
+    let (tx, rx) = channel();
+    let handler = Handler::default().recv_handler(move |packet| {
+        let _ = tx.send(packet.to_vec());
+    });
+    let (first, _second) = Agent::pair_loopback(Handler::default(), handler).unwrap();
+
+    let batch: [&[u8]; 2] = [b"batch1", b"batch2"];
+    assert_eq!(first.send_batch(&batch), Ok(2));
+    for packet in batch {
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(packet.to_vec()));
+    }
+}
+
+#[test]
+fn sub_channels() {
+    logger_init();
+
+    let (tx, rx) = channel();
+    let handler = Handler::default().recv_handler(move |packet| {
+        let _ = tx.send((None::<u16>, packet.to_vec()));
+    });
+    let (first, second) = Agent::pair_loopback(Handler::default(), handler).unwrap();
+
+    let mut receivers = vec![];
+    for id in [1, 2] {
+        let (tx, rx) = channel();
+        let sub = second.channel(id).unwrap();
+        sub.set_recv_handler(move |packet| {
+            let _ = tx.send((Some(id), packet.to_vec()));
+        });
+        receivers.push((sub, rx));
+    }
+    let (control, media) = (first.channel(1).unwrap(), first.channel(2).unwrap());
+    assert!(first.channel(1).is_err());
+
+    media.send(b"media").unwrap();
+    control.send(b"control").unwrap();
+    first.send(b"plain").unwrap();
+
+    let timeout = Duration::from_secs(1);
+    assert_eq!(
+        receivers[0].1.recv_timeout(timeout),
+        Ok((Some(1), b"control".to_vec()))
+    );
+    assert_eq!(
+        receivers[1].1.recv_timeout(timeout),
+        Ok((Some(2), b"media".to_vec()))
+    );
+    assert_eq!(rx.recv_timeout(timeout), Ok((None, b"plain".to_vec())));
+}
+
+#[derive(Debug, PartialEq)]
+enum RaceEvent {
+    State(State),
+    Gathered(String),
+    Recv(Vec<u8>),
+}
+
+/// Start agent on the mux socket, its handler uses the agent from the first callbacks
+fn start_racing(socket: SocketAddr) -> (Agent, Receiver<RaceEvent>) {
+    let (tx, rx) = channel();
+    let states = tx.clone();
+    let packets = tx.clone();
+    let weak = Arc::new(OnceLock::<WeakAgent>::new());
+    let handler = Handler::default()
+        .state_handler(move |state| {
+            let _ = states.send(RaceEvent::State(state));
+        })
+        .gathering_done_handler({
+            let weak = weak.clone();
+            move || {
+                // upgraded handle is released before the event, the receiver may drop the agent
+                let description = weak
+                    .get()
+                    .and_then(WeakAgent::upgrade)
+                    .map(|agent| agent.get_local_description().unwrap());
+                if let Some(description) = description {
+                    let _ = tx.send(RaceEvent::Gathered(description));
+                }
+            }
+        })
+        .recv_handler(move |packet| {
+            let _ = packets.send(RaceEvent::Recv(packet.to_vec()));
+        });
+
+    let prepared = Agent::builder(handler)
+        .without_stun()
+        .with_mux_socket(socket)
+        .prepare()
+        .unwrap();
+    assert!(prepared.downgrade().upgrade().is_none());
+    assert!(weak.set(prepared.downgrade()).is_ok());
+    let agent = prepared.start().unwrap();
+    agent.gather_candidates().unwrap();
+    (agent, rx)
+}
+
+/// Wait for the local description, checking no event is lost before
+fn wait_gathered(rx: &Receiver<RaceEvent>) -> String {
+    let timeout = Duration::from_secs(5);
+    assert_eq!(
+        rx.recv_timeout(timeout),
+        Ok(RaceEvent::State(State::Gathering))
+    );
+    loop {
+        match rx.recv_timeout(timeout).unwrap() {
+            RaceEvent::Gathered(description) => return description,
+            event => log::debug!("event before gathering done: {:?}", event),
+        }
+    }
+}
+
+#[test]
+fn mux_construction_race() {
+    logger_init();
+
+    let timeout = Duration::from_secs(5);
+    let (first, first_rx) = start_racing((Ipv4Addr::LOCALHOST, 0).into());
+    let first_desc = wait_gathered(&first_rx);
+    let socket = SocketAddr::from((Ipv4Addr::LOCALHOST, first.mux_port().unwrap()));
+
+    // agents keep being constructed on the shared libjuice thread while the second one gathers
+    // and connects
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn = spawn({
+        let stop = stop.clone();
+        move || {
+            let mut built = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let (agent, rx) = start_racing(socket);
+                wait_gathered(&rx);
+                drop(agent);
+                built += 1;
+            }
+            built
+        }
+    });
+
+    let (second, second_rx) = start_racing(socket);
+    let second_desc = wait_gathered(&second_rx);
+    second.set_remote_description(first_desc).unwrap();
+    first.set_remote_description(second_desc).unwrap();
+
+    for rx in [&first_rx, &second_rx] {
+        loop {
+            match rx.recv_timeout(timeout).unwrap() {
+                RaceEvent::State(State::Connected | State::Completed) => break,
+                RaceEvent::State(State::Failed) => panic!("agent failed"),
+                _ => {}
+            }
+        }
+    }
+
+    first.send(b"race").unwrap();
+    loop {
+        match second_rx.recv_timeout(timeout).unwrap() {
+            RaceEvent::Recv(packet) => break assert_eq!(packet, b"race"),
+            event => log::debug!("second event: {:?}", event),
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    assert!(churn.join().unwrap() > 0);
+}
+
+#[test]
+fn negotiate() {
+    logger_init();
+
+    let localhost = IpAddr::from(Ipv4Addr::LOCALHOST);
+    let build = || {
+        Agent::builder(Handler::default())
+            .without_stun()
+            .with_bind_address(&localhost)
+            .build()
+            .unwrap()
+    };
+    let (first, second) = (build(), build());
+    let (mut first_signaling, mut second_signaling) = ChannelSignaling::pair();
+
+    let timeout = Duration::from_secs(10);
+    let peer = spawn(move || {
+        second.negotiate(&mut second_signaling, timeout)?;
+        Ok::<_, libjuice_rs::Error>(second)
+    });
+    first.negotiate(&mut first_signaling, timeout).unwrap();
+    let second = peer.join().unwrap().unwrap();
+
+    assert_ne!(
+        first.negotiation_state(),
+        NegotiationState::AwaitingDescription
+    );
+    for agent in [&first, &second] {
+        assert!(matches!(
+            agent.get_state(),
+            State::Connected | State::Completed
+        ));
+    }
+}
+
+#[test]
+fn shared_handle() {
+    logger_init();
+
+    let agent = Agent::builder(Handler::default()).build().unwrap();
+    // weak handle owned by the agent's own handler doesn't keep it alive
+    let weak = agent.downgrade();
+    agent.set_handler(Handler::default().state_handler(move |state| {
+        let _ = &weak;
+        log::info!("state {:?}", state);
+    }));
+
+    let clone = agent.clone();
+    assert_eq!(agent.strong_count(), 2);
+    spawn(move || clone.gather_candidates().unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(agent.strong_count(), 1);
+
+    let weak = agent.downgrade();
+    drop(agent);
+    assert!(weak.upgrade().is_none());
+}
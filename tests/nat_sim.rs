@@ -0,0 +1,101 @@
+#![cfg(feature = "nat-sim")]
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use libjuice_rs::nat_sim::{self, FullConeNat, NatAgent, SymmetricNat};
+use libjuice_rs::{Agent, CandidateType, Handler, State};
+
+include!("../src/test_util.rs");
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait for both agents to leave connecting state, returns whether both connected
+fn wait_connected(first: &NatAgent, second: &NatAgent) -> bool {
+    let deadline = Instant::now() + TIMEOUT;
+    let connected =
+        |a: &NatAgent| matches!(a.agent().get_state(), State::Connected | State::Completed);
+    loop {
+        if connected(first) && connected(second) {
+            return true;
+        }
+        let failed = [first, second]
+            .iter()
+            .any(|a| a.agent().get_state() == State::Failed);
+        if failed || Instant::now() >= deadline {
+            return false;
+        }
+        sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn full_cone_to_symmetric() {
+    logger_init();
+
+    let first = FullConeNat::wrap(Agent::builder(Handler::default()))
+        .with_latency(Duration::from_millis(20))
+        .with_loss(0.1)
+        .build()
+        .unwrap();
+    let second = SymmetricNat::wrap(Agent::builder(Handler::default()))
+        .build()
+        .unwrap();
+
+    let _sim = nat_sim::connect(&first, &second, TIMEOUT).unwrap();
+    assert!(wait_connected(&first, &second));
+
+    // traffic goes through the NAT endpoints, never directly
+    let (_, remote, _, _) = first.agent().selected_pair().unwrap();
+    let (second_local, _, _, _) = second.agent().selected_pair().unwrap();
+    assert_ne!(remote, second_local);
+}
+
+#[test]
+fn symmetric_without_turn() {
+    logger_init();
+
+    let first = SymmetricNat::wrap(Agent::builder(Handler::default()))
+        .build()
+        .unwrap();
+    let second = SymmetricNat::wrap(Agent::builder(Handler::default()))
+        .build()
+        .unwrap();
+
+    let _sim = nat_sim::connect(&first, &second, TIMEOUT).unwrap();
+    assert!(!wait_connected(&first, &second));
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn symmetric_turn_fallback() {
+    use libjuice_rs::{Server, ServerCredentials};
+
+    logger_init();
+
+    let server = Server::builder()
+        .bind_address(&"127.0.0.1:3479".parse().unwrap())
+        .with_port_range(7100, 7200)
+        .add_credentials(ServerCredentials::new("nat_sim", "nat_sim_password", None).unwrap())
+        .build()
+        .unwrap();
+
+    let build = || {
+        let builder = Agent::builder(Handler::default())
+            .add_turn_server(
+                "127.0.0.1",
+                server.get_port(),
+                "nat_sim",
+                "nat_sim_password",
+            )
+            .unwrap();
+        SymmetricNat::wrap(builder).build().unwrap()
+    };
+    let (first, second) = (build(), build());
+
+    let _sim = nat_sim::connect(&first, &second, TIMEOUT).unwrap();
+    assert!(wait_connected(&first, &second));
+
+    let (_, _, local, remote) = first.agent().selected_pair().unwrap();
+    assert!(local == CandidateType::Relayed || remote == CandidateType::Relayed);
+}
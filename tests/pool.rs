@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use libjuice_rs::{AgentEvent, AgentPool, ConcurrencyMode};
+
+include!("../src/test_util.rs");
+
+#[test]
+fn pool_gathering() {
+    logger_init();
+
+    let (pool, events) = AgentPool::builder()
+        .with_concurrency_mode(ConcurrencyMode::Poll)
+        .with_gathering_limit(1)
+        .build();
+
+    let bind = "127.0.0.1".parse().unwrap();
+    for id in 0..3 {
+        pool.add_agent(id, |builder| builder.with_bind_address(&bind))
+            .unwrap();
+    }
+    assert_eq!(pool.len(), 3);
+    assert!(pool.add_agent(0, |builder| builder).is_err());
+
+    for id in 0..3 {
+        pool.gather(&id).unwrap();
+    }
+
+    let mut done = HashSet::new();
+    let mut seqs = HashSet::new();
+    while done.len() < 3 {
+        let e = events.recv_timeout(Duration::from_secs(10)).unwrap();
+        log::info!("#{} agent {} event {:?}", e.seq, e.id, e.event);
+        assert!(seqs.insert(e.seq));
+        if e.event == AgentEvent::GatheringDone {
+            done.insert(e.id);
+        }
+    }
+
+    assert!(pool.remove(&0).is_some());
+    assert_eq!(pool.len(), 2);
+}
@@ -1,6 +1,9 @@
+#![cfg(feature = "server")]
+
 use libjuice_rs::{Agent, Handler, Server, ServerCredentials};
 use std::sync::mpsc::channel;
 use std::sync::{Arc, Barrier};
+use std::time::Duration;
 
 include!("../src/test_util.rs");
 
@@ -108,3 +111,44 @@ fn test_server() {
 
     run_server(server);
 }
+
+#[test]
+fn relay_addresses() {
+    logger_init();
+
+    let server_address = "127.0.0.1:3479".parse().unwrap();
+    let server = Server::builder()
+        .bind_address(&server_address)
+        .with_port_range(7001, 8000)
+        .add_credentials(server_credentials())
+        .build()
+        .unwrap();
+    let server_port = server.get_port();
+
+    let (done_tx, done_rx) = channel();
+    let (relay_tx, relay_rx) = channel();
+    let handler = Handler::default()
+        .relay_ready_handler(move |addr| {
+            log::info!("relay ready: {}", addr);
+            let _ = relay_tx.send(addr);
+        })
+        .gathering_done_handler(move || {
+            let _ = done_tx.send(());
+        });
+    let agent = Agent::builder(handler)
+        .add_turn_server("127.0.0.1", server_port, USER, PASS)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert!(agent.relay_addresses().is_empty());
+
+    agent.gather_candidates().unwrap();
+    done_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    let relays = agent.relay_addresses();
+    assert!(!relays.is_empty());
+    assert!(relays
+        .iter()
+        .all(|addr| (7001..=8000).contains(&addr.port())));
+    assert_eq!(relay_rx.try_iter().collect::<Vec<_>>(), relays);
+}